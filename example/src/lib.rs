@@ -15,34 +15,702 @@ mod wasm;
 mod tests {
     extern crate std;
 
-    use gear_test_runtime::ControlSignal;
-    use gtest::{Program, System};
+    use std::{
+        string::String,
+        sync::{Arc, RwLock},
+        vec,
+        vec::Vec,
+    };
 
-    #[test]
-    fn program_can_be_initialized() {
-        let system = System::new();
-        system.init_logger();
+    use codec::Decode;
+    use gear_test_runtime::{
+        ControlError, ControlSignal, ProgressSignal, RunMode, RuntimeInfo, SessionInfo, TestFilter,
+        TestOrder, TestUpdate, PROTOCOL_VERSION,
+    };
+    use gtest::{Program, RunResult, System, WasmProgram};
 
-        // test_program
+    /// Sender of all control signals, allowed to run tests as the deployer.
+    const OWNER: u64 = 0;
+
+    fn test_program(system: &System) -> Program<'_> {
         let test_program = Program::from_file(
-            &system,
+            system,
             "../target/wasm32-unknown-unknown/debug/example_test.opt.wasm",
         );
-        let res = test_program.send_bytes(0, b"dummy");
+        // empty init payload allows only the deployer to run tests
+        let res = test_program.send_bytes(OWNER, b"");
         assert!(!res.main_failed());
 
+        test_program
+    }
+
+    /// Control bus collecting progress updates, and logs shipped with them.
+    #[derive(Debug, Default, Clone)]
+    struct ControlBus(
+        Arc<RwLock<Vec<(String, TestUpdate)>>>,
+        Arc<RwLock<Vec<String>>>,
+    );
+
+    impl WasmProgram for ControlBus {
+        fn init(&mut self, _payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+            Ok(None)
+        }
+
+        fn handle(&mut self, payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+            let ProgressSignal {
+                test_info,
+                update,
+                logs,
+            } = ProgressSignal::decode(&mut &payload[..]).expect("Failed to decode progress");
+            self.0.write().unwrap().push((test_info.name, update));
+            self.1.write().unwrap().extend(logs);
+            Ok(None)
+        }
+
+        fn handle_reply(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn handle_signal(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn state(&mut self) -> Result<Vec<u8>, &'static str> {
+            Ok(vec![])
+        }
+    }
+
+    fn reply_payload(res: &RunResult) -> &[u8] {
+        res.log()
+            .iter()
+            .find(|log| log.reply_to() == Some(res.sent_message_id()))
+            .expect("No reply")
+            .payload()
+    }
+
+    fn test_signal(system: &System, test_program: &Program, max_concurrency: u32) -> ControlSignal {
         // actual program
         let code_hash =
             system.submit_code("../target/wasm32-unknown-unknown/debug/example.opt.wasm");
 
-        // actual test run
+        ControlSignal::Test {
+            code_hash: code_hash.into_bytes().into(),
+            codes: vec![],
+            control_bus: test_program.id().into_bytes().into(),
+            filter: Default::default(),
+            run_mode: Default::default(),
+            timeout_blocks: None,
+            expiry_blocks: None,
+            max_concurrency,
+            fail_fast: false,
+            retries: 0,
+            order: Default::default(),
+        }
+    }
+
+    /// Session options varied by tests, the rest are as `cargo gtest` sends them by default.
+    #[derive(Debug, Clone, Copy)]
+    struct Options {
+        max_concurrency: u32,
+        expiry_blocks: Option<u32>,
+        fail_fast: bool,
+        order: TestOrder,
+    }
+
+    impl Default for Options {
+        fn default() -> Self {
+            Options {
+                max_concurrency: 1,
+                expiry_blocks: None,
+                fail_fast: false,
+                order: TestOrder::Declaration,
+            }
+        }
+    }
+
+    /// Runs tests matching `patterns` exactly in a new session, collecting its progress.
+    ///
+    /// Returns the bus along with the result of the `Test` message, its id is the session id.
+    fn run_filtered(
+        system: &System,
+        test_program: &Program,
+        patterns: &[&str],
+        options: Options,
+    ) -> (ControlBus, RunResult) {
+        let bus = ControlBus::default();
+        let control = Program::mock(system, bus.clone());
+        let res = control.send_bytes(OWNER, b"");
+        assert!(!res.main_failed());
+
         let res = test_program.send(
-            0,
+            OWNER,
             ControlSignal::Test {
-                code_hash: code_hash.into_bytes().into(),
-                control_bus: test_program.id().into_bytes().into(),
+                code_hash: system
+                    .submit_code("../target/wasm32-unknown-unknown/debug/example.opt.wasm")
+                    .into_bytes()
+                    .into(),
+                codes: vec![],
+                control_bus: control.id().into_bytes().into(),
+                filter: TestFilter {
+                    patterns: patterns.iter().copied().map(String::from).collect(),
+                    skip: vec![],
+                    exact: true,
+                },
+                run_mode: RunMode::Normal,
+                timeout_blocks: None,
+                expiry_blocks: options.expiry_blocks,
+                max_concurrency: options.max_concurrency,
+                fail_fast: options.fail_fast,
+                retries: 0,
+                order: options.order,
+            },
+        );
+        assert!(!res.main_failed());
+
+        (bus, res)
+    }
+
+    /// Final update reported for the test.
+    fn outcome<'a>(updates: &'a [(String, TestUpdate)], name: &str) -> &'a TestUpdate {
+        updates
+            .iter()
+            .rev()
+            .find(|(test_name, _)| test_name == name)
+            .map(|(_, update)| update)
+            .unwrap_or_else(|| panic!("No progress reported for {}", name))
+    }
+
+    fn run_tests(max_concurrency: u32) {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+
+        // actual test run
+        let res = test_program.send(OWNER, test_signal(&system, &test_program, max_concurrency));
+        assert!(!res.main_failed());
+    }
+
+    #[test]
+    fn handshake_reports_protocol_version() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+
+        let res = test_program.send(OWNER, ControlSignal::Hello);
+        assert!(!res.main_failed());
+
+        let info = RuntimeInfo::decode(&mut reply_payload(&res)).expect("Failed to decode info");
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn program_can_be_initialized() {
+        run_tests(1);
+    }
+
+    #[test]
+    fn passing_tests_succeed() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let tests = [
+            "example::wasm::sync_no_session",
+            "example::wasm::session_by_value",
+            "example::wasm::result",
+            "example::wasm::typed_call",
+            // each case is a test of its own
+            "example::wasm::instances::single",
+            "example::wasm::instances::several",
+        ];
+        let (bus, _) = run_filtered(&system, &test_program, &tests, Options::default());
+
+        let updates = bus.0.read().unwrap();
+        for name in tests {
+            assert!(
+                matches!(outcome(&updates, name), TestUpdate::Success(_)),
+                "{}: {:?}",
+                name,
+                outcome(&updates, name)
+            );
+        }
+        let started = updates
+            .iter()
+            .filter(|(_, update)| matches!(update, TestUpdate::Start))
+            .count();
+        assert_eq!(started, tests.len());
+    }
+
+    #[test]
+    fn ignored_test_reports_reason() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::slow"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(
+            &updates[..],
+            [(_, TestUpdate::Ignored(Some(reason)))] if reason == "runs only with --include-ignored"
+        ));
+    }
+
+    #[test]
+    fn err_fails_test_with_debug_text() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::result_err"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        let TestUpdate::Fail(hint, _) = outcome(&updates, "example::wasm::result_err") else {
+            panic!("Err did not fail the test: {:?}", updates);
+        };
+        assert_eq!(hint, "\"not implemented\"");
+    }
+
+    #[test]
+    fn gas_is_reported_unless_test_panics() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::good", "example::wasm::bad"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(
+            outcome(&updates, "example::wasm::good"),
+            TestUpdate::Success(Some(gas_burned)) if *gas_burned > 0
+        ));
+        assert!(matches!(
+            outcome(&updates, "example::wasm::bad"),
+            TestUpdate::Fail(_, None)
+        ));
+    }
+
+    #[test]
+    fn tests_can_run_concurrently() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::good", "example::wasm::timely"],
+            Options {
+                max_concurrency: 4,
+                ..Default::default()
+            },
+        );
+
+        // both tests start before any of them finishes
+        let updates = bus.0.read().unwrap();
+        let first_finished = updates
+            .iter()
+            .position(|(_, update)| !matches!(update, TestUpdate::Start))
+            .expect("No test finished");
+        assert_eq!(first_finished, 2);
+        assert!(updates[..2]
+            .iter()
+            .all(|(_, update)| matches!(update, TestUpdate::Start)));
+    }
+
+    #[test]
+    fn hanging_test_times_out() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        // `hangs` is declared after `good`, so it runs first
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::good", "example::wasm::hangs"],
+            Options {
+                order: TestOrder::Reverse,
+                ..Default::default()
+            },
+        );
+        system.spend_blocks(10);
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(
+            outcome(&updates, "example::wasm::hangs"),
+            TestUpdate::Timeout(3)
+        ));
+        // next test still runs after the timeout
+        assert!(matches!(
+            outcome(&updates, "example::wasm::good"),
+            TestUpdate::Success(_)
+        ));
+    }
+
+    #[test]
+    fn overlapping_sessions_are_isolated() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let session = |code_path: &str| {
+            let bus = ControlBus::default();
+            let control = Program::mock(&system, bus.clone());
+            let res = control.send_bytes(OWNER, b"");
+            assert!(!res.main_failed());
+
+            let res = test_program.send(
+                OWNER,
+                ControlSignal::Test {
+                    code_hash: system.submit_code(code_path).into_bytes().into(),
+                    codes: vec![],
+                    control_bus: control.id().into_bytes().into(),
+                    filter: TestFilter {
+                        patterns: vec!["example::wasm::pong_after_sleep".into()],
+                        skip: vec![],
+                        exact: true,
+                    },
+                    run_mode: RunMode::Normal,
+                    timeout_blocks: None,
+                    expiry_blocks: None,
+                    max_concurrency: 1,
+                    fail_fast: false,
+                    retries: 0,
+                    order: Default::default(),
+                },
+            );
+            assert!(!res.main_failed());
+
+            bus
+        };
+
+        // Test only reaches its session after the sleep, so it has to be suspended between
+        // polls, not yet having read its session, when the other session starts.
+        let suspended = |bus: &ControlBus| {
+            let updates = bus.0.read().unwrap();
+            matches!(&updates[..], [(_, TestUpdate::Start)])
+        };
+
+        let pong = session("../target/wasm32-unknown-unknown/debug/example.opt.wasm");
+        assert!(suspended(&pong));
+        // test program does not answer PING with PONG
+        let no_pong = session("../target/wasm32-unknown-unknown/debug/example_test.opt.wasm");
+        assert!(suspended(&pong));
+        assert!(suspended(&no_pong));
+        system.spend_blocks(10);
+
+        let outcome = |bus: &ControlBus| {
+            let updates = bus.0.read().unwrap();
+            let (_, update) = updates.last().expect("No progress reported");
+            matches!(update, TestUpdate::Success(_))
+        };
+        assert!(outcome(&pong));
+        assert!(!outcome(&no_pong));
+    }
+
+    #[test]
+    fn aborted_session_ignores_late_replies() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, res) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::pong_after_sleep"],
+            Options::default(),
+        );
+        let session_id = res.sent_message_id().into_bytes().into();
+
+        let res = test_program.send(OWNER, ControlSignal::Sessions);
+        let sessions = Vec::<SessionInfo>::decode(&mut reply_payload(&res)).expect("Bad reply");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, session_id);
+
+        let res = test_program.send(OWNER, ControlSignal::Abort(session_id));
+        assert!(reply_payload(&res).is_empty());
+
+        // sleeping test wakes up and replies to the aborted session
+        for res in system.spend_blocks(10) {
+            assert!(!res.main_failed());
+        }
+
+        let updates = bus.0.read().unwrap();
+        assert!(updates
+            .iter()
+            .all(|(_, update)| matches!(update, TestUpdate::Start)));
+
+        let res = test_program.send(OWNER, ControlSignal::Sessions);
+        let sessions = Vec::<SessionInfo>::decode(&mut reply_payload(&res)).expect("Bad reply");
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn expired_session_is_not_listed() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::pong_after_sleep"],
+            Options {
+                expiry_blocks: Some(1),
+                ..Default::default()
             },
         );
+
+        let res = test_program.send(OWNER, ControlSignal::Sessions);
+        let sessions = Vec::<SessionInfo>::decode(&mut reply_payload(&res)).expect("Bad reply");
+        assert_eq!(sessions.len(), 1);
+
+        // test sleeps 2 blocks, longer than the session lives
+        for res in system.spend_blocks(10) {
+            assert!(!res.main_failed());
+        }
+
+        let updates = bus.0.read().unwrap();
+        assert!(updates
+            .iter()
+            .all(|(_, update)| matches!(update, TestUpdate::Start)));
+
+        let res = test_program.send(OWNER, ControlSignal::Sessions);
+        let sessions = Vec::<SessionInfo>::decode(&mut reply_payload(&res)).expect("Bad reply");
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn fail_fast_skips_remaining_tests() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        // `bad` is declared after `good`, reverse order runs it first
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::good", "example::wasm::bad"],
+            Options {
+                fail_fast: true,
+                order: TestOrder::Reverse,
+                ..Default::default()
+            },
+        );
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(
+            outcome(&updates, "example::wasm::bad"),
+            TestUpdate::Fail(..)
+        ));
+        assert!(matches!(
+            outcome(&updates, "example::wasm::good"),
+            TestUpdate::Skipped
+        ));
+    }
+
+    #[test]
+    fn failed_attempt_is_retried() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::passes_on_retry"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        let updates = updates.iter().map(|(_, update)| update).collect::<Vec<_>>();
+        assert!(matches!(
+            updates[..],
+            [
+                TestUpdate::Start,
+                TestUpdate::Retry(1, _),
+                TestUpdate::Success(_)
+            ]
+        ));
+        assert_eq!(*bus.1.read().unwrap(), ["attempt 1", "attempt 2"]);
+    }
+
+    #[test]
+    fn logs_of_panicked_test_are_reported() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::logs_before_panic"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(updates.last(), Some((_, TestUpdate::Fail(..)))));
+        assert_eq!(*bus.1.read().unwrap(), ["deploying testee"]);
+    }
+
+    #[test]
+    fn tests_run_in_requested_order() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::good", "example::wasm::timely"],
+            Options {
+                order: TestOrder::Reverse,
+                ..Default::default()
+            },
+        );
+
+        let updates = bus.0.read().unwrap();
+        let started = updates
+            .iter()
+            .filter(|(_, update)| matches!(update, TestUpdate::Start))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(started, ["example::wasm::timely", "example::wasm::good"]);
+    }
+
+    #[test]
+    fn failure_reports_panic_message() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &["example::wasm::bad"],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        let Some((_, TestUpdate::Fail(hint, _))) = updates.last() else {
+            panic!("Test did not fail: {:?}", updates);
+        };
+        // `assert_eq!` output and its location
+        assert!(hint.contains("left"), "{}", hint);
+        assert!(hint.contains("src/wasm.rs"), "{}", hint);
+    }
+
+    #[test]
+    fn should_panic_checks_panic_message() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &[
+                "example::wasm::panics",
+                "example::wasm::panics_with_other_message",
+            ],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        assert!(matches!(
+            outcome(&updates, "example::wasm::panics"),
+            TestUpdate::Success(_)
+        ));
+        let TestUpdate::Fail(hint, _) =
+            outcome(&updates, "example::wasm::panics_with_other_message")
+        else {
+            panic!("Panic with other message did not fail the test");
+        };
+        assert!(hint.contains("Expected PONG"), "{}", hint);
+    }
+
+    #[test]
+    fn hook_err_fails_only_tests_of_its_module() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+        let (bus, _) = run_filtered(
+            &system,
+            &test_program,
+            &[
+                "example::wasm::good",
+                "example::wasm::failing_hook::never_runs",
+            ],
+            Options::default(),
+        );
+
+        let updates = bus.0.read().unwrap();
+        let finished = updates
+            .iter()
+            .filter(|(_, update)| !matches!(update, TestUpdate::Start))
+            .collect::<Vec<_>>();
+        // `never_runs` has retries, but failed hook is reported once, without retry
+        assert_eq!(finished.len(), 2);
+        for (name, update) in finished {
+            match name.as_str() {
+                "example::wasm::good" => assert!(matches!(update, TestUpdate::Success(_))),
+                _ => {
+                    let TestUpdate::HookFailed(hint) = update else {
+                        panic!("Unexpected update of {}: {:?}", name, update);
+                    };
+                    assert!(hint.contains("testee is not ready"), "{}", hint);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_owner_can_run_tests() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+
+        let res = test_program.send(42, test_signal(&system, &test_program, 1));
+        assert!(!res.main_failed());
+
+        let error = ControlError::decode(&mut reply_payload(&res)).expect("Failed to decode error");
+        assert!(matches!(error, ControlError::Unauthorized(_)));
+    }
+
+    #[test]
+    fn tests_are_executed_only_internally() {
+        let system = System::new();
+        system.init_logger();
+
+        let test_program = test_program(&system);
+
+        let res = test_program.send(
+            OWNER,
+            ControlSignal::WrapExecute(Default::default(), 0, Default::default()),
+        );
         assert!(!res.main_failed());
+
+        let error = ControlError::decode(&mut reply_payload(&res)).expect("Failed to decode error");
+        assert!(matches!(error, ControlError::Unauthorized(_)));
     }
 }
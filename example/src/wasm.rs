@@ -1,5 +1,7 @@
-use gstd::{msg, prelude::*, ActorId, CodeId};
+use core::sync::atomic::{AtomicU32, Ordering};
+use gstd::{msg, prelude::*, ActorId};
 
+#[gear_test_codegen::runtime]
 #[gstd::async_main]
 async fn main() {
     let payload = msg::load_bytes().expect("Failed to load payload");
@@ -9,17 +11,21 @@ async fn main() {
     }
 }
 
-async fn create_this(code_hash: &CodeId) -> ActorId {
-    let (_, actor_id) =
-        gstd::prog::ProgramGenerator::create_program_bytes(code_hash.clone(), b"PING", 0)
-            .expect("Failed to create this/self");
+async fn create_this(context: &gear_test_runtime::SessionData) -> ActorId {
+    context
+        .deploy(())
+        .await
+        .expect("Failed to create this/self")
+}
 
-    actor_id
+#[gear_test_codegen::before_each]
+async fn deployed(context: &gear_test_runtime::SessionData) -> ActorId {
+    create_this(context).await
 }
 
 #[gear_test_codegen::test]
 async fn good(context: &gear_test_runtime::SessionData) {
-    let this = create_this(&context.testee()).await;
+    let this: ActorId = context.fixture("deployed");
 
     let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)
         .expect("failed to send")
@@ -31,7 +37,7 @@ async fn good(context: &gear_test_runtime::SessionData) {
 
 #[gear_test_codegen::test]
 async fn bad(context: &gear_test_runtime::SessionData) {
-    let this = create_this(&context.testee()).await;
+    let this = create_this(context).await;
 
     let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)
         .expect("failed to send")
@@ -40,3 +46,144 @@ async fn bad(context: &gear_test_runtime::SessionData) {
 
     assert_eq!(result, b"NOTPOING")
 }
+
+#[gear_test_codegen::test]
+async fn pong_after_sleep(context: gear_test_runtime::SessionData) {
+    // other sessions can run meanwhile
+    gstd::exec::sleep_for(2).await;
+
+    let this = create_this(&context).await;
+
+    let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)
+        .expect("failed to send")
+        .await
+        .expect("Program to handle simple PING!!1");
+
+    assert_eq!(result, b"PONG")
+}
+
+#[gear_test_codegen::test]
+async fn typed_call(
+    context: &gear_test_runtime::SessionData,
+) -> Result<(), gear_test_runtime::CallError> {
+    let this = create_this(context).await;
+
+    // fixed size arrays are encoded as is
+    let reply: [u8; 4] = context.call(this, *b"PING").await?;
+
+    assert_eq!(&reply, b"PONG");
+    Ok(())
+}
+
+#[gear_test_codegen::test]
+fn sync_no_session() {
+    assert_eq!(b"PING".len(), b"PONG".len())
+}
+
+#[gear_test_codegen::test]
+async fn session_by_value(context: gear_test_runtime::SessionData) {
+    create_this(&context).await;
+}
+
+#[gear_test_codegen::test]
+async fn result(context: &gear_test_runtime::SessionData) -> Result<(), gstd::errors::Error> {
+    let this = create_this(context).await;
+
+    let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)?.await?;
+
+    assert_eq!(result, b"PONG");
+
+    Ok(())
+}
+
+#[gear_test_codegen::test]
+fn result_err() -> Result<(), &'static str> {
+    Err("not implemented")
+}
+
+#[gear_test_codegen::test(case(single, 1), case(several, 3))]
+async fn instances(context: &gear_test_runtime::SessionData, count: usize) {
+    for _ in 0..count {
+        let this = create_this(context).await;
+
+        let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)
+            .expect("failed to send")
+            .await
+            .expect("Program to handle simple PING!!1");
+
+        assert_eq!(result, b"PONG")
+    }
+}
+
+#[gear_test_codegen::test(should_panic(expected = "NOTPONG"))]
+async fn panics(context: &gear_test_runtime::SessionData) {
+    let this = create_this(context).await;
+
+    let result: Vec<u8> = msg::send_bytes_for_reply(this, b"PING", 0, 0)
+        .expect("failed to send")
+        .await
+        .expect("Program to handle simple PING!!1");
+
+    assert!(result == b"NOTPONG", "Expected NOTPONG");
+}
+
+#[gear_test_codegen::test(should_panic(expected = "NOTPONG"))]
+fn panics_with_other_message() {
+    panic!("Expected PONG");
+}
+
+#[gear_test_codegen::test(timeout_blocks = 10)]
+async fn timely(context: &gear_test_runtime::SessionData) {
+    create_this(context).await;
+}
+
+#[gear_test_codegen::test(timeout_blocks = 3)]
+async fn hangs() {
+    // much longer than the timeout
+    gstd::exec::sleep_for(1_000).await;
+}
+
+/// Attempts of `passes_on_retry`, kept between attempts since failing with `Err` does not
+/// revert program state (unlike panic).
+static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+#[gear_test_codegen::test(retries = 1)]
+fn passes_on_retry(context: &gear_test_runtime::SessionData) -> Result<(), &'static str> {
+    let attempt = ATTEMPTS.fetch_add(1, Ordering::Relaxed) + 1;
+    context.log(format!("attempt {}", attempt));
+
+    match attempt {
+        1 => Err("first attempt fails"),
+        _ => Ok(()),
+    }
+}
+
+#[gear_test_codegen::test]
+async fn logs_before_panic(context: &gear_test_runtime::SessionData) {
+    // kept when the test waits for the deployment, so it is shipped along with the failure
+    context.log("deploying testee");
+    let this = create_this(context).await;
+
+    panic!("testee {:?} deployed, failing on purpose", this);
+}
+
+/// Hooks of this module run only around its tests.
+mod failing_hook {
+    #[gear_test_codegen::before_each]
+    fn not_ready() -> Result<(), &'static str> {
+        Err("testee is not ready")
+    }
+
+    // failed hook is not retried
+    #[gear_test_codegen::test(retries = 1)]
+    fn never_runs() {
+        panic!("test should not run after failed hook");
+    }
+}
+
+#[gear_test_codegen::test(ignore = "runs only with --include-ignored")]
+async fn slow(context: &gear_test_runtime::SessionData) {
+    for _ in 0..10 {
+        create_this(context).await;
+    }
+}
@@ -4,6 +4,9 @@ use wasm_graph::{
     Memory, Module, SegmentLocation, Table,
 };
 
+/// Export name prefix of functions generated by `gear_test_codegen::test`.
+const TEST_EXPORT_PREFIX: &str = "__gtest_";
+
 struct Context {
     module: Module,
 }
@@ -18,7 +21,7 @@ impl Context {
             .exports
             .iter()
             .filter_map(|export| {
-                if export.name.starts_with("test_") {
+                if export.name.starts_with(TEST_EXPORT_PREFIX) {
                     match export.local {
                         ExportLocal::Func(ref func_ref) => Some(func_ref.clone()),
                         _ => None,
@@ -161,6 +164,10 @@ impl Context {
         self.get_exported_impl("run_tests")
     }
 
+    pub fn init_tests_impl(&self) -> Result<EntryRef<Func>> {
+        self.get_exported_impl("init_tests")
+    }
+
     pub fn handle_impl(&self) -> Result<EntryRef<Func>> {
         self.get_exported_impl("handle")
     }
@@ -177,6 +184,8 @@ pub fn extract(module: parity_wasm::elements::Module) -> Result<parity_wasm::ele
     let test_funcs = context.test_funcs();
     let handle_impl = context.handle_impl()?;
     let run_tests_impl = context.run_tests_impl()?;
+    // only checking presence, it is exported as `init` below
+    context.init_tests_impl()?;
 
     let i32_ptr = context.store_func_ptrs(&test_funcs[..])?;
 
@@ -200,9 +209,17 @@ pub fn extract(module: parity_wasm::elements::Module) -> Result<parity_wasm::ele
 
     let mut module = context.to_module();
 
-    module
-        .exports
-        .retain(|export| !(export.name.starts_with("test_") || export.name == "run_tests"));
+    // program's own `init` is dropped, test program is initialized with access config instead
+    module.exports.retain(|export| {
+        !(export.name.starts_with(TEST_EXPORT_PREFIX)
+            || export.name == "run_tests"
+            || export.name == "init")
+    });
+    for export in module.exports.iter_mut() {
+        if export.name == "init_tests" {
+            export.name = "init".to_string();
+        }
+    }
 
     let result = module.generate()?;
 
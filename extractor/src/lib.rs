@@ -6,17 +6,21 @@
 //!     ... some code ...
 //! }
 //!
-//! pub unsafe extern "C" fn test_some_test() {
+//! #[export_name = "__gtest_crate::module::some_test"]
+//! pub unsafe extern "C" fn __gtest_some_test() {
 //!     ... some test code ...
 //! }
 //!
 //! to this binary:
 //!
 //! pub unsafe extern "C" handle {
-//!     test_some_test();
+//!     run_tests(&[__gtest_some_test]);
 //! }
 //!
-//! Note that original "... some code ..." is removed
+//! Note that original "... some code ..." is removed. Only exports in the `__gtest_`
+//! namespace are treated as tests.
+//!
+//! Original `init` export, if any, is replaced with `init_tests` of the test runtime.
 
 #[cfg(test)]
 mod tests;
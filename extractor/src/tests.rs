@@ -36,8 +36,9 @@ fn simple() {
             (table 1 1 funcref)
             (export "handle" (func 0))
             (export "run_tests" (func 3))
-            (export "test_some_test" (func 1))
-            (export "test_another_test" (func 2))
+            (export "init_tests" (func 4))
+            (export "__gtest_example::some_test" (func 1))
+            (export "__gtest_example::module::some_test" (func 2))
             (elem (i32.const 0) func 0)
             (func (;0;))
             (func (;1;)
@@ -52,6 +53,10 @@ fn simple() {
                 i32.const 4
                 drop
             )
+            (func (;4;)
+                i32.const 6
+                drop
+            )
         )
     "#,
     );
@@ -78,8 +83,13 @@ fn simple() {
                 i32.const 4
                 drop
             )
+            (func (;4;) (type 0)
+                i32.const 6
+                drop
+            )
             (table (;0;) 3 3 funcref)
             (export "handle" (func 0))
+            (export "init" (func 4))
             (elem (;0;) (i32.const 0) func 0)
             (elem (;1;) (i32.const 1) func 1 2)
             (data (;0;) (i32.const 65536) "\02\00\00\00\01\00\00\00\02\00\00\00")
@@ -91,3 +101,193 @@ fn simple() {
 
     assert_bytes(&actual_bytes[..], &expected_bytes[..]);
 }
+
+#[test]
+fn user_exports_are_not_tests() {
+    let original_bytes = bytes(
+        r#"
+        (module
+            (type (;0;) (func))
+            (type (;1;) (func (param i32)))
+            (import "env" "memory" (memory 1))
+            (table 1 1 funcref)
+            (export "handle" (func 0))
+            (export "run_tests" (func 3))
+            (export "init_tests" (func 4))
+            (export "__gtest_example::some_test" (func 1))
+            (export "test_user_func" (func 2))
+            (elem (i32.const 0) func 0)
+            (func (;0;))
+            (func (;1;)
+                i32.const 0
+                drop
+            )
+            (func (;2;)
+                i32.const 2
+                drop
+            )
+            (func (type 1) (;3;)
+                i32.const 4
+                drop
+            )
+            (func (;4;)
+                i32.const 6
+                drop
+            )
+        )
+    "#,
+    );
+
+    let expected_bytes = bytes(
+        r#"
+        (module
+            (type (;0;) (func))
+            (type (;1;) (func (param i32)))
+            (import "env" "memory" (memory (;0;) 2))
+            (func (;0;) (type 0)
+              i32.const 65536
+              call 3
+            )
+            (func (;1;) (type 0)
+              i32.const 0
+              drop
+            )
+            (func (;2;) (type 0)
+              i32.const 2
+              drop
+            )
+            (func (type 1) (;3;)
+                i32.const 4
+                drop
+            )
+            (func (;4;) (type 0)
+                i32.const 6
+                drop
+            )
+            (table (;0;) 2 2 funcref)
+            (export "handle" (func 0))
+            (export "init" (func 4))
+            (export "test_user_func" (func 2))
+            (elem (;0;) (i32.const 0) func 0)
+            (elem (;1;) (i32.const 1) func 1)
+            (data (;0;) (i32.const 65536) "\01\00\00\00\01\00\00\00")
+        )
+    "#,
+    );
+
+    let actual_bytes = super::extract_from_bytes(&original_bytes[..]).expect("Failed to extract");
+
+    assert_bytes(&actual_bytes[..], &expected_bytes[..]);
+}
+
+#[test]
+fn init_is_replaced() {
+    let original_bytes = bytes(
+        r#"
+        (module
+            (type (;0;) (func))
+            (type (;1;) (func (param i32)))
+            (import "env" "memory" (memory 1))
+            (table 1 1 funcref)
+            (export "init" (func 2))
+            (export "handle" (func 0))
+            (export "run_tests" (func 3))
+            (export "init_tests" (func 4))
+            (export "__gtest_example::some_test" (func 1))
+            (elem (i32.const 0) func 0)
+            (func (;0;))
+            (func (;1;)
+                i32.const 0
+                drop
+            )
+            (func (;2;)
+                i32.const 2
+                drop
+            )
+            (func (type 1) (;3;)
+                i32.const 4
+                drop
+            )
+            (func (;4;)
+                i32.const 6
+                drop
+            )
+        )
+    "#,
+    );
+
+    let expected_bytes = bytes(
+        r#"
+        (module
+            (type (;0;) (func))
+            (type (;1;) (func (param i32)))
+            (import "env" "memory" (memory (;0;) 2))
+            (func (;0;) (type 0)
+              i32.const 65536
+              call 3
+            )
+            (func (;1;) (type 0)
+              i32.const 0
+              drop
+            )
+            (func (;2;) (type 0)
+              i32.const 2
+              drop
+            )
+            (func (type 1) (;3;)
+                i32.const 4
+                drop
+            )
+            (func (;4;) (type 0)
+                i32.const 6
+                drop
+            )
+            (table (;0;) 2 2 funcref)
+            (export "handle" (func 0))
+            (export "init" (func 4))
+            (elem (;0;) (i32.const 0) func 0)
+            (elem (;1;) (i32.const 1) func 1)
+            (data (;0;) (i32.const 65536) "\01\00\00\00\01\00\00\00")
+        )
+    "#,
+    );
+
+    let actual_bytes = super::extract_from_bytes(&original_bytes[..]).expect("Failed to extract");
+
+    assert_bytes(&actual_bytes[..], &expected_bytes[..]);
+}
+
+#[test]
+fn init_tests_is_required() {
+    let original_bytes = bytes(
+        r#"
+        (module
+            (type (;0;) (func))
+            (type (;1;) (func (param i32)))
+            (import "env" "memory" (memory 1))
+            (table 1 1 funcref)
+            (export "handle" (func 0))
+            (export "run_tests" (func 2))
+            (export "__gtest_example::some_test" (func 1))
+            (elem (i32.const 0) func 0)
+            (func (;0;))
+            (func (;1;)
+                i32.const 0
+                drop
+            )
+            (func (type 1) (;2;)
+                i32.const 4
+                drop
+            )
+        )
+    "#,
+    );
+
+    let error = super::extract_from_bytes(&original_bytes[..])
+        .expect_err("Module without `init_tests` should be rejected");
+
+    assert_eq!(
+        error.to_string(),
+        "'init_tests' function is not exported, which is invalid"
+    );
+}
@@ -133,11 +133,11 @@ mod tests {
 
         let wat = r#"
             (module
-                (func (export "test_func1") (param i32 i32) (result i32)
+                (func (export "__gtest_func1") (param i32 i32) (result i32)
                     get_local 0
                     get_local 1
                     i32.add)
-                (func (export "test_func2") (param i32 i32) (result i32)
+                (func (export "__gtest_func2") (param i32 i32) (result i32)
                     get_local 0
                     get_local 1
                     i32.sub)
@@ -149,6 +149,7 @@ mod tests {
                     get_local 0
                     i32.const 1
                     i32.add)
+                (func (export "init_tests"))
                 (memory (export "memory") 1)
                 (table (export "table") 1 anyfunc)
             )
@@ -0,0 +1,320 @@
+//! Command line arguments of `cargo gtest`
+//!
+//! Same layout as `cargo test`: everything before `--` goes to `cargo build`,
+//! everything after it controls the test run itself.
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use gear_test_runtime::{RunMode, TestFilter, TestOrder};
+
+#[derive(Debug, Default, Clone)]
+pub struct TestArgs {
+    pub filter: TestFilter,
+    pub run_mode: RunMode,
+    /// Default timeout for each test, in blocks.
+    pub timeout_blocks: Option<u32>,
+    /// Number of tests run at once, same as libtest `--test-threads`.
+    pub test_threads: u32,
+    /// List tests instead of running them, same as libtest `--list`.
+    pub list: bool,
+    /// Wasm files of testee dependencies, by name.
+    pub codes: Vec<(String, PathBuf)>,
+    /// Stop at the first failed test, same as `cargo test --fail-fast`.
+    pub fail_fast: bool,
+    /// Default number of times failed test is run again.
+    pub retries: u32,
+    /// Order of running tests, shuffled with `--shuffle` or `--shuffle-seed`.
+    pub order: TestOrder,
+    /// Print logs of passed tests too, same as libtest `--nocapture`.
+    pub nocapture: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Args {
+    pub cargo_args: Vec<String>,
+    pub test_args: TestArgs,
+}
+
+impl Args {
+    /// Parse arguments of the `cargo gtest` invocation.
+    pub fn from_env() -> Result<Self> {
+        // skipping binary name and `gtest` subcommand name
+        Self::parse(std::env::args().skip(2))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut cargo_args = Vec::new();
+        let mut fail_fast = false;
+
+        for arg in args.by_ref() {
+            match arg.as_str() {
+                "--" => break,
+                // not a `cargo build` argument
+                "--fail-fast" => fail_fast = true,
+                _ => cargo_args.push(arg),
+            }
+        }
+
+        let mut test_args = TestArgs::parse(args)?;
+        test_args.fail_fast = fail_fast;
+
+        Ok(Args {
+            cargo_args,
+            test_args,
+        })
+    }
+}
+
+/// Parses numeric value of the `option`.
+fn number<T: FromStr>(option: &str, value: Option<String>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("'{}' requires an argument", option))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("'{}' should be a number, got '{}'", option, value))
+}
+
+/// Seed for `--shuffle` without `--shuffle-seed`.
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+impl TestArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut test_args = TestArgs {
+            test_threads: 1,
+            ..Default::default()
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--exact" => test_args.filter.exact = true,
+                "--ignored" => test_args.run_mode = RunMode::IgnoredOnly,
+                "--include-ignored" => test_args.run_mode = RunMode::IncludeIgnored,
+                "--list" => test_args.list = true,
+                "--nocapture" => test_args.nocapture = true,
+                "--reverse" => test_args.order = TestOrder::Reverse,
+                "--shuffle" => {
+                    // explicit seed wins, whatever the order of options
+                    if !matches!(test_args.order, TestOrder::Shuffle(_)) {
+                        test_args.order = TestOrder::Shuffle(random_seed());
+                    }
+                }
+                "--shuffle-seed" => {
+                    test_args.order = TestOrder::Shuffle(number(&arg, args.next())?);
+                }
+                "--code" => {
+                    let code = args
+                        .next()
+                        .ok_or_else(|| anyhow!("'--code' requires an argument"))?;
+                    let (name, path) = code.split_once('=').ok_or_else(|| {
+                        anyhow!("'--code' should be 'name=path.wasm', got '{}'", code)
+                    })?;
+                    if test_args.codes.iter().any(|(known, _)| known == name) {
+                        bail!("Duplicate '--code' name '{}'", name);
+                    }
+                    test_args
+                        .codes
+                        .push((name.to_string(), PathBuf::from(path)));
+                }
+                "--skip" => {
+                    let pattern = args
+                        .next()
+                        .ok_or_else(|| anyhow!("'--skip' requires an argument"))?;
+                    test_args.filter.skip.push(pattern);
+                }
+                "--timeout-blocks" => {
+                    test_args.timeout_blocks = Some(number(&arg, args.next())?);
+                }
+                "--retries" => {
+                    test_args.retries = number(&arg, args.next())?;
+                }
+                "--test-threads" => {
+                    test_args.test_threads = number(&arg, args.next())?;
+                    if test_args.test_threads == 0 {
+                        bail!("'--test-threads' should be greater than 0");
+                    }
+                }
+                _ => {
+                    if let Some(pattern) = arg.strip_prefix("--skip=") {
+                        test_args.filter.skip.push(pattern.to_string());
+                    } else if arg.starts_with('-') {
+                        bail!("Unrecognized test option: '{}'", arg);
+                    } else {
+                        test_args.filter.patterns.push(arg);
+                    }
+                }
+            }
+        }
+
+        Ok(test_args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).expect("Failed to parse args")
+    }
+
+    #[test]
+    fn cargo_args_only() {
+        let args = parse(&["--release", "-p", "example"]);
+
+        assert_eq!(args.cargo_args, ["--release", "-p", "example"]);
+        assert!(args.test_args.filter.patterns.is_empty());
+    }
+
+    #[test]
+    fn test_args_after_separator() {
+        let args = parse(&[
+            "--release",
+            "--",
+            "transfer",
+            "--exact",
+            "--skip",
+            "slow",
+            "--skip=flaky",
+        ]);
+
+        assert_eq!(args.cargo_args, ["--release"]);
+        assert_eq!(args.test_args.filter.patterns, ["transfer"]);
+        assert_eq!(args.test_args.filter.skip, ["slow", "flaky"]);
+        assert!(args.test_args.filter.exact);
+        assert!(!args.test_args.list);
+    }
+
+    #[test]
+    fn list() {
+        let args = parse(&["--", "--list", "wasm::"]);
+
+        assert!(args.test_args.list);
+        assert_eq!(args.test_args.filter.patterns, ["wasm::"]);
+    }
+
+    #[test]
+    fn ignored_run_mode() {
+        assert_eq!(parse(&[]).test_args.run_mode, RunMode::Normal);
+        assert_eq!(
+            parse(&["--", "--ignored"]).test_args.run_mode,
+            RunMode::IgnoredOnly
+        );
+        assert_eq!(
+            parse(&["--", "--include-ignored"]).test_args.run_mode,
+            RunMode::IncludeIgnored
+        );
+    }
+
+    #[test]
+    fn timeout_blocks() {
+        assert_eq!(parse(&[]).test_args.timeout_blocks, None);
+        assert_eq!(
+            parse(&["--", "--timeout-blocks", "20"])
+                .test_args
+                .timeout_blocks,
+            Some(20)
+        );
+        assert!(Args::parse(["--", "--timeout-blocks", "many"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn retries() {
+        assert_eq!(parse(&[]).test_args.retries, 0);
+        assert_eq!(parse(&["--", "--retries", "2"]).test_args.retries, 2);
+        assert!(Args::parse(["--", "--retries"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_threads() {
+        assert_eq!(parse(&[]).test_args.test_threads, 1);
+        assert_eq!(
+            parse(&["--", "--test-threads", "4"]).test_args.test_threads,
+            4
+        );
+        assert!(Args::parse(["--", "--test-threads", "0"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn codes() {
+        let args = parse(&[
+            "--",
+            "--code",
+            "token=token.opt.wasm",
+            "--code",
+            "dex=../dex.wasm",
+        ]);
+
+        assert_eq!(
+            args.test_args.codes,
+            [
+                ("token".to_string(), PathBuf::from("token.opt.wasm")),
+                ("dex".to_string(), PathBuf::from("../dex.wasm"))
+            ]
+        );
+        assert!(Args::parse(["--", "--code", "token.wasm"].map(String::from)).is_err());
+        assert!(Args::parse(
+            ["--", "--code", "token=a.wasm", "--code", "token=b.wasm"].map(String::from)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn shuffle() {
+        assert_eq!(parse(&[]).test_args.order, TestOrder::Declaration);
+        assert!(matches!(
+            parse(&["--", "--shuffle"]).test_args.order,
+            TestOrder::Shuffle(_)
+        ));
+        assert_eq!(
+            parse(&["--", "--shuffle-seed", "42"]).test_args.order,
+            TestOrder::Shuffle(42)
+        );
+        assert_eq!(
+            parse(&["--", "--shuffle-seed", "42", "--shuffle"])
+                .test_args
+                .order,
+            TestOrder::Shuffle(42)
+        );
+        assert!(Args::parse(["--", "--shuffle-seed", "-1"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn reverse() {
+        assert_eq!(
+            parse(&["--", "--reverse"]).test_args.order,
+            TestOrder::Reverse
+        );
+    }
+
+    #[test]
+    fn nocapture() {
+        assert!(!parse(&[]).test_args.nocapture);
+        assert!(parse(&["--", "--nocapture"]).test_args.nocapture);
+    }
+
+    #[test]
+    fn fail_fast() {
+        assert!(!parse(&[]).test_args.fail_fast);
+
+        let args = parse(&["--release", "--fail-fast", "--", "transfer"]);
+        assert!(args.test_args.fail_fast);
+        assert_eq!(args.cargo_args, ["--release"]);
+        assert_eq!(args.test_args.filter.patterns, ["transfer"]);
+    }
+
+    #[test]
+    fn unknown_test_option() {
+        assert!(Args::parse(["--".to_string(), "--bogus".to_string()]).is_err());
+    }
+}
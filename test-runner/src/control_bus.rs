@@ -11,6 +11,8 @@ use gear_test_runtime::{ProgressSignal, TestInfo, TestUpdate};
 #[derive(Debug, Default)]
 pub struct ControlBus {
     running_state: Arc<RwLock<State>>,
+    /// Print logs of all tests, not only of failed ones.
+    nocapture: bool,
 }
 
 #[derive(Debug, Default)]
@@ -18,7 +20,18 @@ pub struct State {
     started: u32,
     failed: u32,
     succeded: u32,
-    unfinished: Vec<String>,
+    ignored: u32,
+    hook_failed: u32,
+    timed_out: u32,
+    gas_burned: u64,
+    skipped: Vec<String>,
+    /// Indices of tests which failed at least one attempt.
+    retried: Vec<u32>,
+    flaky: Vec<String>,
+    /// Started tests, with updates of concurrent tests interleaving.
+    unfinished: Vec<TestInfo>,
+    /// Number of updates of any kind, to tell whether tests still make progress.
+    progress: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -26,12 +39,22 @@ pub struct Report {
     pub total_started: u32,
     pub total_failed: u32,
     pub total_succeded: u32,
+    pub total_ignored: u32,
+    pub total_hook_failed: u32,
+    pub total_timed_out: u32,
+    /// Gas burned by all tests which did not panic.
+    pub total_gas_burned: u64,
+    /// Tests not run because `before_all` hook failed or, in fail-fast mode, earlier test failed.
+    pub skipped: Vec<String>,
+    /// Tests which passed only after retry.
+    pub flaky: Vec<String>,
     pub unfinished: Vec<String>,
 }
 
 impl State {
-    pub fn submit_fail(&mut self, test_info: TestInfo) {
+    pub fn submit_fail(&mut self, test_info: TestInfo, gas_burned: Option<u64>) {
         self.failed += 1;
+        self.gas_burned += gas_burned.unwrap_or_default();
         self.remove(test_info);
     }
 
@@ -40,20 +63,65 @@ impl State {
         self.append(test_info);
     }
 
-    pub fn submit_success(&mut self, test_info: TestInfo) {
+    pub fn submit_success(&mut self, test_info: TestInfo, gas_burned: Option<u64>) {
         self.succeded += 1;
+        if self.is_retried(&test_info) {
+            self.flaky.push(test_info.name.clone());
+        }
+        self.gas_burned += gas_burned.unwrap_or_default();
+        self.remove(test_info);
+    }
+
+    pub fn submit_hook_failed(&mut self, test_info: TestInfo) {
+        self.hook_failed += 1;
         self.remove(test_info);
     }
 
+    pub fn submit_timeout(&mut self, test_info: TestInfo) {
+        self.timed_out += 1;
+        self.remove(test_info);
+    }
+
+    pub fn submit_retry(&mut self, test_info: TestInfo) {
+        if !self.is_retried(&test_info) {
+            self.retried.push(test_info.index);
+        }
+    }
+
+    /// Whether test failed at least one attempt.
+    pub fn is_retried(&self, test_info: &TestInfo) -> bool {
+        self.retried.contains(&test_info.index)
+    }
+
+    pub fn submit_skipped(&mut self, test_info: TestInfo) {
+        self.skipped.push(test_info.name);
+    }
+
+    pub fn submit_ignored(&mut self, _test_info: TestInfo) {
+        self.ignored += 1;
+    }
+
+    /// Number of updates of any kind, including failed attempts.
+    pub fn progress(&self) -> u32 {
+        self.progress
+    }
+
+    pub fn has_unfinished(&self) -> bool {
+        !self.unfinished.is_empty()
+    }
+
     fn remove(&mut self, test_info: TestInfo) {
-        let pos = self.unfinished.iter().position(|e| *e == test_info.name);
+        let pos = self
+            .unfinished
+            .iter()
+            .position(|e| e.index == test_info.index);
         if let Some(pos) = pos {
             self.unfinished.swap_remove(pos);
         }
     }
 
     fn append(&mut self, test_info: TestInfo) {
-        self.unfinished.push(test_info.name)
+        self.unfinished.push(test_info)
     }
 
     pub fn report(&self) -> Report {
@@ -61,7 +129,17 @@ impl State {
             total_started: self.started,
             total_succeded: self.succeded,
             total_failed: self.failed,
-            unfinished: self.unfinished.clone(),
+            total_ignored: self.ignored,
+            total_hook_failed: self.hook_failed,
+            total_timed_out: self.timed_out,
+            total_gas_burned: self.gas_burned,
+            skipped: self.skipped.clone(),
+            flaky: self.flaky.clone(),
+            unfinished: self
+                .unfinished
+                .iter()
+                .map(|test_info| test_info.name.clone())
+                .collect(),
         }
     }
 }
@@ -70,7 +148,8 @@ impl Report {
     pub fn success(&self) -> bool {
         if self.unfinished.len() > 0 {
             false
-        } else if self.total_failed != 0 {
+        } else if self.total_failed != 0 || self.total_hook_failed != 0 || self.total_timed_out != 0
+        {
             false
         } else if self.total_started != self.total_succeded {
             false
@@ -84,28 +163,58 @@ impl fmt::Display for Report {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(
             f,
-            "test result: {}. {} passed; {} failed;",
+            "test result: {}. {} passed; {} failed; {} ignored;",
             match self.success() {
                 true => "ok".green(),
                 false => "fail".red(),
             },
             self.total_succeded,
-            self.total_failed
+            self.total_failed,
+            self.total_ignored
         )?;
 
+        writeln!(f, "total gas burned: {}", self.total_gas_burned)?;
+
+        if self.total_hook_failed != 0 {
+            writeln!(f, "hooks failed: {}", self.total_hook_failed)?;
+        }
+
+        if self.total_timed_out != 0 {
+            writeln!(f, "timed out: {}", self.total_timed_out)?;
+        }
+
+        if !self.flaky.is_empty() {
+            writeln!(f, "flaky tests: [{}]", self.flaky.join(", "))?;
+        }
+
+        if !self.skipped.is_empty() {
+            writeln!(f, "skipped tests: [{}]", self.skipped.join(", "))?;
+        }
+
         if !self.unfinished.is_empty() {
-            write!(f, "unfinished tests: [")?;
-            for unfinished in self.unfinished.iter() {
-                write!(f, "{}", unfinished)?;
-            }
-            writeln!(f, "]")?;
+            writeln!(f, "unfinished tests: [{}]", self.unfinished.join(", "))?;
         }
 
         Ok(())
     }
 }
 
+/// Gas suffix of the test status line, if gas is known.
+fn gas_note(gas_burned: Option<u64>) -> String {
+    match gas_burned {
+        Some(gas_burned) => format!(" (gas: {})", gas_burned),
+        None => String::new(),
+    }
+}
+
 impl ControlBus {
+    pub fn new(nocapture: bool) -> Self {
+        ControlBus {
+            nocapture,
+            ..Default::default()
+        }
+    }
+
     pub fn running_state(&self) -> Arc<RwLock<State>> {
         self.running_state.clone()
     }
@@ -121,26 +230,126 @@ impl WasmProgram for ControlBus {
         let progress_signal =
             ProgressSignal::decode(&mut &payload[..]).expect("Failed to decode progress signal!");
 
-        let ProgressSignal { test_info, update } = progress_signal;
+        let ProgressSignal {
+            test_info,
+            update,
+            logs,
+        } = progress_signal;
+        let name = test_info.name.clone();
+        self.running_state.write().unwrap().progress += 1;
+        let failed = matches!(
+            update,
+            TestUpdate::Fail(..)
+                | TestUpdate::HookFailed(_)
+                | TestUpdate::Timeout(_)
+                | TestUpdate::Retry(..)
+        );
 
         match update {
             TestUpdate::Start => {
                 self.running_state.write().unwrap().submit_start(test_info);
             }
-            TestUpdate::Success => {
-                println!("test {} ... {}", test_info.name, "ok".green());
+            TestUpdate::Success(gas_burned) => {
+                let flaky = match self.running_state.read().unwrap().is_retried(&test_info) {
+                    true => format!(" ({})", "flaky".yellow()),
+                    false => String::new(),
+                };
+                println!(
+                    "test {} ... {}{}{}",
+                    test_info.name,
+                    "ok".green(),
+                    flaky,
+                    gas_note(gas_burned)
+                );
+                self.running_state
+                    .write()
+                    .unwrap()
+                    .submit_success(test_info, gas_burned);
+            }
+            TestUpdate::Fail(hint, gas_burned) => {
+                println!(
+                    "test {} ... {}{}",
+                    test_info.name,
+                    "fail".red(),
+                    gas_note(gas_burned)
+                );
+                println!("\t --- ERROR REPORT @ {}", test_info.name);
+                println!("{}", hint);
+                println!("\t --- END OF REPORT @ {}", test_info.name);
                 self.running_state
                     .write()
                     .unwrap()
-                    .submit_success(test_info);
+                    .submit_fail(test_info, gas_burned);
             }
-            TestUpdate::Fail(hint) => {
-                println!("test {} ... {}", test_info.name, "fail".red());
+            TestUpdate::HookFailed(hint) => {
+                println!("test {} ... {}", test_info.name, "hook failed".red());
                 println!("\t --- ERROR REPORT @ {}", test_info.name);
                 println!("{}", hint);
                 println!("\t --- END OF REPORT @ {}", test_info.name);
-                self.running_state.write().unwrap().submit_fail(test_info);
+                self.running_state
+                    .write()
+                    .unwrap()
+                    .submit_hook_failed(test_info);
+            }
+            TestUpdate::Timeout(blocks) => {
+                println!(
+                    "test {} ... {} ({} blocks)",
+                    test_info.name,
+                    "timeout".red(),
+                    blocks
+                );
+                self.running_state
+                    .write()
+                    .unwrap()
+                    .submit_timeout(test_info);
+            }
+            TestUpdate::Retry(attempt, hint) => {
+                println!(
+                    "test {} ... {} (attempt {} failed)",
+                    test_info.name,
+                    "retrying".yellow(),
+                    attempt
+                );
+                println!(
+                    "\t --- ERROR REPORT @ {} (attempt {})",
+                    test_info.name, attempt
+                );
+                println!("{}", hint);
+                println!("\t --- END OF REPORT @ {}", test_info.name);
+                self.running_state.write().unwrap().submit_retry(test_info);
+            }
+            TestUpdate::Skipped => {
+                println!("test {} ... {}", test_info.name, "skipped".yellow());
+                self.running_state
+                    .write()
+                    .unwrap()
+                    .submit_skipped(test_info);
+            }
+            TestUpdate::Ignored(reason) => {
+                match reason {
+                    Some(reason) => {
+                        println!(
+                            "test {} ... {}, {}",
+                            test_info.name,
+                            "ignored".yellow(),
+                            reason
+                        )
+                    }
+                    None => println!("test {} ... {}", test_info.name, "ignored".yellow()),
+                }
+                self.running_state
+                    .write()
+                    .unwrap()
+                    .submit_ignored(test_info);
+            }
+        }
+
+        if !logs.is_empty() && (failed || self.nocapture) {
+            println!("\t --- LOGS @ {}", name);
+            for line in logs {
+                println!("{}", line);
             }
+            println!("\t --- END OF LOGS @ {}", name);
         }
 
         Ok(None)
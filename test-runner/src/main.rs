@@ -1,14 +1,27 @@
 use anyhow::Context;
-use gear_test_runtime::ControlSignal;
-use gtest::{Program, System};
+use args::{Args, TestArgs};
+use codec::Decode;
+use gear_test_runtime::{ControlSignal, RuntimeInfo, TestListing, TestOrder, PROTOCOL_VERSION};
+use gtest::{Program, RunResult, System};
 use std::io::{prelude::*, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use tempfile::NamedTempFile;
 
+mod args;
 mod control_bus;
 
-pub fn run_from_bin_path(bin_path_file: impl AsRef<Path>) -> anyhow::Result<()> {
+/// Blocks to spend at once while tests wait for timed out replies.
+const WAIT_BLOCKS_STEP: u32 = 10;
+
+/// Give up waiting after this many blocks without any test progress, unless some test may time
+/// out later than that.
+const MAX_IDLE_BLOCKS: u32 = 1_000;
+
+pub fn run_from_bin_path(
+    bin_path_file: impl AsRef<Path>,
+    test_args: &TestArgs,
+) -> anyhow::Result<()> {
     let wasm_base = std::fs::read_to_string(bin_path_file.as_ref().to_path_buf())
         .with_context(|| format!("Reading {:?}", bin_path_file.as_ref().to_path_buf()))?;
 
@@ -19,49 +32,148 @@ pub fn run_from_bin_path(bin_path_file: impl AsRef<Path>) -> anyhow::Result<()>
 
     let test_bin_path = bin_base.join(PathBuf::from(format!("{wasm_base}_test.opt.wasm")));
 
-    run_tests(wasm_bin_path, test_bin_path)
+    run_tests(wasm_bin_path, test_bin_path, test_args)
 }
 
-pub fn run_from_dir(directory: impl AsRef<Path>) -> anyhow::Result<()> {
+pub fn run_from_dir(directory: impl AsRef<Path>, test_args: &TestArgs) -> anyhow::Result<()> {
     let mut path = directory.as_ref().to_path_buf();
     path.push(".binpath");
 
-    run_from_bin_path(path)
+    run_from_bin_path(path, test_args)
+}
+
+/// Payload of the reply to the message sent in `res`.
+fn reply_payload(res: &RunResult) -> anyhow::Result<&[u8]> {
+    res.log()
+        .iter()
+        .find(|log| log.reply_to() == Some(res.sent_message_id()))
+        .map(|log| log.payload())
+        .context("No reply from the test program")
+}
+
+/// Checks that test program speaks the same protocol version as this runner.
+fn handshake(test_program: &Program) -> anyhow::Result<RuntimeInfo> {
+    let res = test_program.send(0, ControlSignal::Hello);
+    if res.main_failed() {
+        anyhow::bail!(
+            "Test program does not support handshake, it is likely built with older gear-test-runtime. \
+            Rebuild it with gear-test-runtime of protocol version {}",
+            PROTOCOL_VERSION
+        );
+    }
+
+    let info = RuntimeInfo::decode(&mut reply_payload(&res)?)
+        .context("Failed to decode test program runtime info")?;
+    if info.protocol_version != PROTOCOL_VERSION {
+        anyhow::bail!(
+            "Test program uses protocol version {} (gear-test-runtime {}), but this runner supports only version {}. \
+            Rebuild test program or use matching cargo-gtest",
+            info.protocol_version,
+            info.runtime_version,
+            PROTOCOL_VERSION
+        );
+    }
+
+    Ok(info)
+}
+
+/// Checks that file exists and looks like wasm, since `System::submit_code` panics otherwise.
+fn check_wasm(path: &Path) -> anyhow::Result<()> {
+    let code = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !code.starts_with(b"\0asm") {
+        anyhow::bail!("{} is not a wasm file", path.display());
+    }
+    Ok(())
 }
 
 pub fn run_tests(
     program_wasm_path: impl AsRef<Path>,
     progrm_test_path: impl AsRef<Path>,
+    test_args: &TestArgs,
 ) -> anyhow::Result<()> {
     let system = System::new();
     system.init_logger();
 
     // test_program
     let test_program = Program::from_file(&system, progrm_test_path);
-    let res = test_program.send_bytes(0, vec![]); // empty initialization for test program
+    let res = test_program.send_bytes(0, vec![]); // empty initialization, only runner can run tests
     assert!(!res.main_failed());
+    handshake(&test_program)?;
+
+    let listing = list(&test_program, test_args)?;
 
     // code under test (code_hash)
     let code_hash = system.submit_code(program_wasm_path);
+    // and programs it depends on
+    let codes = test_args
+        .codes
+        .iter()
+        .map(|(name, path)| {
+            check_wasm(path).with_context(|| format!("Invalid '--code {}'", name))?;
+            Ok((name.clone(), system.submit_code(path).into_bytes().into()))
+        })
+        .collect::<anyhow::Result<_>>()?;
 
     // control bus program (for results telemetry)
-    let control_bus = control_bus::ControlBus::default();
+    let control_bus = control_bus::ControlBus::new(test_args.nocapture);
     let running_state = control_bus.running_state();
     let control = Program::mock(&system, control_bus);
     // apparently it also should be initialized
     let res = control.send_bytes(0, vec![]);
     assert!(!res.main_failed());
 
+    if let TestOrder::Shuffle(seed) = test_args.order {
+        println!(
+            "shuffle seed: {} (replay with `--shuffle-seed {}`)",
+            seed, seed
+        );
+    }
+
     // actual test run
     let res = test_program.send(
         0,
         ControlSignal::Test {
             code_hash: code_hash.into_bytes().into(),
+            codes,
             control_bus: control.id().into_bytes().into(),
+            filter: test_args.filter.clone(),
+            run_mode: test_args.run_mode,
+            timeout_blocks: test_args.timeout_blocks,
+            expiry_blocks: None,
+            max_concurrency: test_args.test_threads,
+            fail_fast: test_args.fail_fast,
+            retries: test_args.retries,
+            order: test_args.order,
         },
     );
     assert!(!res.main_failed());
 
+    // Test attempt is the test with its `before_each` and `after_each` hooks, each of them may
+    // wait up to the longest timeout before the runtime reports it.
+    let longest_timeout = listing
+        .iter()
+        .filter_map(|test| test.timeout_blocks)
+        .chain(test_args.timeout_blocks)
+        .max();
+    let idle_limit = longest_timeout.map_or(MAX_IDLE_BLOCKS, |blocks| {
+        blocks.saturating_mul(3).max(MAX_IDLE_BLOCKS)
+    });
+
+    // tests waiting for reply with timeout are woken up only when blocks pass
+    let mut progress = running_state.read().unwrap().progress();
+    let mut idle_blocks = 0;
+    while running_state.read().unwrap().has_unfinished() && idle_blocks <= idle_limit {
+        system.spend_blocks(WAIT_BLOCKS_STEP);
+
+        let now_progress = running_state.read().unwrap().progress();
+        if now_progress == progress {
+            idle_blocks += WAIT_BLOCKS_STEP;
+        } else {
+            progress = now_progress;
+            idle_blocks = 0;
+        }
+    }
+
     let report = running_state.read().unwrap().report();
     println!("\n{}", report);
 
@@ -72,18 +184,61 @@ pub fn run_tests(
     Ok(())
 }
 
-fn generate_cargo_args() -> Vec<String> {
+/// Count with the noun, pluralized the same way as by libtest.
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+/// Tests of the test program selected by the filter and run mode.
+fn list(test_program: &Program, test_args: &TestArgs) -> anyhow::Result<Vec<TestListing>> {
+    let res = test_program.send(
+        0,
+        ControlSignal::List {
+            filter: test_args.filter.clone(),
+            run_mode: test_args.run_mode,
+        },
+    );
+    assert!(!res.main_failed());
+
+    Vec::<TestListing>::decode(&mut reply_payload(&res)?).context("Failed to decode test list")
+}
+
+/// Prints tests of the test program in libtest `--list` format, without running them.
+pub fn list_tests(progrm_test_path: impl AsRef<Path>, test_args: &TestArgs) -> anyhow::Result<()> {
+    let system = System::new();
+
+    let test_program = Program::from_file(&system, progrm_test_path);
+    let res = test_program.send_bytes(0, vec![]); // empty initialization, only runner can run tests
+    assert!(!res.main_failed());
+
+    handshake(&test_program)?;
+
+    let listing = list(&test_program, test_args)?;
+    for test in listing.iter() {
+        println!("{}: test", test.test_info.name);
+    }
+    println!("\n{}, 0 benchmarks", plural(listing.len(), "test"));
+
+    Ok(())
+}
+
+fn generate_cargo_args(args: &Args) -> Vec<String> {
     ["build".to_string()]
         .into_iter()
-        .chain(std::env::args().skip(2))
+        .chain(args.cargo_args.iter().cloned())
         .collect()
 }
 
 fn main() -> anyhow::Result<()> {
+    let args = Args::from_env()?;
+
     let builder_artifacts_file = NamedTempFile::new()?;
     let builder_artifacts_path = builder_artifacts_file.path().as_os_str();
 
-    let mut cargo_args = generate_cargo_args();
+    let mut cargo_args = generate_cargo_args(&args);
     cargo_args.push("--config".to_string());
     cargo_args.push(format!(
         "env.GEAR_BUILDER_ARTIFACTS=\"{}\"",
@@ -100,7 +255,7 @@ fn main() -> anyhow::Result<()> {
     if !build_out.status.success() {
         anyhow::bail!(
             "Cargo command failed (cargo {})",
-            generate_cargo_args().join(" ")
+            generate_cargo_args(&args).join(" ")
         );
     }
 
@@ -112,10 +267,38 @@ fn main() -> anyhow::Result<()> {
             anyhow::bail!("Got this from artifacts dump: '{}'. This is invalid, should be '<wasm_path>|<wasm_test_path>'", line);
         }
 
-        run_tests(paths[0], paths[1])?;
+        if args.test_args.list {
+            list_tests(paths[1], &args.test_args)?;
+        } else {
+            run_tests(paths[0], paths[1], &args.test_args)?;
+        }
     }
 
     // file for gear_test_builder artifacts report;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plural_matches_libtest() {
+        assert_eq!(plural(0, "test"), "0 tests");
+        assert_eq!(plural(1, "test"), "1 test");
+        assert_eq!(plural(2, "test"), "2 tests");
+    }
+
+    #[test]
+    fn check_wasm_rejects_missing_and_non_wasm_files() {
+        assert!(check_wasm(Path::new("no/such/file.wasm")).is_err());
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"not wasm").unwrap();
+        assert!(check_wasm(file.path()).is_err());
+
+        std::fs::write(file.path(), b"\0asm\x01\0\0\0").unwrap();
+        assert!(check_wasm(file.path()).is_ok());
+    }
+}
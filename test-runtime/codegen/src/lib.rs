@@ -1,31 +1,642 @@
-
 //! Provides macros for async runtime of Gear programs.
 
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span};
-use quote::quote;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// `case(label, args...)` argument of the `test` attribute.
+struct TestCase {
+    label: Ident,
+    args: Vec<syn::Expr>,
+}
+
+impl syn::parse::Parse for TestCase {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let label = input.parse()?;
+        let mut args = Vec::new();
+        while !input.is_empty() {
+            input.parse::<syn::Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            args.push(input.parse()?);
+        }
+
+        Ok(TestCase { label, args })
+    }
+}
+
+/// Arguments of the `test` attribute.
+#[derive(Default)]
+struct TestAttrs {
+    /// `should_panic` with optional `expected` substring of the panic message.
+    should_panic: Option<Option<syn::LitStr>>,
+    /// `ignore` with optional reason.
+    ignore: Option<Option<syn::LitStr>>,
+    /// `timeout_blocks = N`, overriding session default.
+    timeout_blocks: Option<syn::LitInt>,
+    /// `retries = N`, overriding session default.
+    retries: Option<syn::LitInt>,
+    /// Parameter sets, each registered as a separate test.
+    cases: Vec<TestCase>,
+}
+
+impl TestAttrs {
+    fn parse(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("should_panic") {
+            let mut expected = None;
+            if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("expected") {
+                        expected = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported `should_panic` property, expected `expected`"))
+                    }
+                })?;
+            }
+            self.should_panic = Some(expected);
+            Ok(())
+        } else if meta.path.is_ident("ignore") {
+            let reason = if meta.input.peek(syn::Token![=]) {
+                Some(meta.value()?.parse()?)
+            } else {
+                None
+            };
+            self.ignore = Some(reason);
+            Ok(())
+        } else if meta.path.is_ident("timeout_blocks") {
+            let timeout_blocks: syn::LitInt = meta.value()?.parse()?;
+            timeout_blocks.base10_parse::<u32>()?;
+            self.timeout_blocks = Some(timeout_blocks);
+            Ok(())
+        } else if meta.path.is_ident("retries") {
+            let retries: syn::LitInt = meta.value()?.parse()?;
+            retries.base10_parse::<u32>()?;
+            self.retries = Some(retries);
+            Ok(())
+        } else if meta.path.is_ident("case") {
+            let content;
+            syn::parenthesized!(content in meta.input);
+            let case: TestCase = content.parse()?;
+            if self.cases.iter().any(|other| other.label == case.label) {
+                return Err(syn::Error::new_spanned(
+                    &case.label,
+                    format!("duplicate case `{}`", case.label),
+                ));
+            }
+            self.cases.push(case);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported `test` attribute"))
+        }
+    }
+
+    fn should_panic(&self) -> proc_macro2::TokenStream {
+        match self.should_panic {
+            None => quote! { gear_test_runtime::ShouldPanic::No },
+            Some(None) => quote! { gear_test_runtime::ShouldPanic::Yes },
+            Some(Some(ref expected)) => {
+                quote! { gear_test_runtime::ShouldPanic::YesWithMessage(#expected) }
+            }
+        }
+    }
+
+    fn ignore(&self) -> proc_macro2::TokenStream {
+        match self.ignore {
+            None => quote! {},
+            Some(None) => quote! { .with_ignore(None) },
+            Some(Some(ref reason)) => quote! { .with_ignore(Some(#reason)) },
+        }
+    }
+
+    fn timeout_blocks(&self) -> proc_macro2::TokenStream {
+        match self.timeout_blocks {
+            None => quote! {},
+            Some(ref blocks) => quote! { .with_timeout_blocks(#blocks) },
+        }
+    }
+
+    fn retries(&self) -> proc_macro2::TokenStream {
+        match self.retries {
+            None => quote! {},
+            Some(ref retries) => quote! { .with_retries(#retries) },
+        }
+    }
+}
+
+/// How test function takes the session.
+enum SessionArg {
+    None,
+    /// `&SessionData`
+    ByRef,
+    /// `SessionData`
+    ByValue,
+}
+
+/// Supported shape of the test function.
+struct TestSignature {
+    is_async: bool,
+    session: SessionArg,
+}
+
+fn is_session_data(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "SessionData" && segment.arguments.is_none()),
+        syn::Type::Paren(paren) => is_session_data(&paren.elem),
+        _ => false,
+    }
+}
+
+fn is_result(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Result"),
+        _ => false,
+    }
+}
 
+/// Returns `None` if parameter is not a session.
+fn session_arg(ty: &syn::Type) -> syn::Result<Option<SessionArg>> {
+    match ty {
+        syn::Type::Reference(reference) if is_session_data(&reference.elem) => {
+            match reference.mutability {
+                Some(mutability) => Err(syn::Error::new_spanned(
+                    mutability,
+                    "session cannot be taken by mutable reference, use `&SessionData`",
+                )),
+                None => Ok(Some(SessionArg::ByRef)),
+            }
+        }
+        ty if is_session_data(ty) => Ok(Some(SessionArg::ByValue)),
+        _ => Ok(None),
+    }
+}
+
+/// Checks test return type, returning its span if it is not `()`.
+fn test_output(sig: &syn::Signature, attrs: &TestAttrs) -> syn::Result<Option<Span>> {
+    let syn::ReturnType::Type(_, ref ty) = sig.output else {
+        return Ok(None);
+    };
+
+    if matches!(**ty, syn::Type::Tuple(ref tuple) if tuple.elems.is_empty()) {
+        return Ok(None);
+    }
+    if attrs.should_panic.is_some() {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "`should_panic` test should return `()`",
+        ));
+    }
+    if !is_result(ty) {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "test should return `()` or `Result<(), E>`",
+        ));
+    }
+
+    Ok(Some(ty.span()))
+}
+
+impl TestSignature {
+    fn parse(sig: &syn::Signature, cases: &[TestCase]) -> syn::Result<Self> {
+        if let Some(ref constness) = sig.constness {
+            return Err(syn::Error::new_spanned(constness, "test cannot be `const`"));
+        }
+        if let Some(ref unsafety) = sig.unsafety {
+            return Err(syn::Error::new_spanned(unsafety, "test cannot be `unsafe`"));
+        }
+        if let Some(ref abi) = sig.abi {
+            return Err(syn::Error::new_spanned(abi, "test cannot have custom ABI"));
+        }
+        if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+            return Err(syn::Error::new_spanned(
+                &sig.generics,
+                "test cannot be generic",
+            ));
+        }
+        if let Some(ref variadic) = sig.variadic {
+            return Err(syn::Error::new_spanned(variadic, "test cannot be variadic"));
+        }
+        let mut params = Vec::new();
+        for input in sig.inputs.iter() {
+            match input {
+                syn::FnArg::Receiver(receiver) => {
+                    return Err(syn::Error::new_spanned(receiver, "test cannot take `self`"));
+                }
+                syn::FnArg::Typed(param) => params.push(param),
+            }
+        }
+
+        let session = match params.first() {
+            Some(param) => session_arg(&param.ty)?,
+            None => None,
+        };
+        let case_params = match session {
+            Some(_) => &params[1..],
+            None => &params[..],
+        };
+
+        if cases.is_empty() {
+            match (case_params.first(), &session) {
+                (Some(param), None) => {
+                    return Err(syn::Error::new_spanned(
+                        &param.ty,
+                        "expected `&SessionData` or `SessionData` parameter",
+                    ));
+                }
+                (Some(param), Some(_)) => {
+                    return Err(syn::Error::new_spanned(
+                        param,
+                        "test parameters besides session require `case(...)` arguments",
+                    ));
+                }
+                (None, _) => {}
+            }
+        }
+
+        for case in cases.iter() {
+            if case.args.len() != case_params.len() {
+                return Err(syn::Error::new_spanned(
+                    &case.label,
+                    format!(
+                        "case `{}` provides {} argument(s), but test takes {}",
+                        case.label,
+                        case.args.len(),
+                        case_params.len()
+                    ),
+                ));
+            }
+        }
+
+        Ok(TestSignature {
+            is_async: sig.asyncness.is_some(),
+            session: session.unwrap_or(SessionArg::None),
+        })
+    }
+
+    /// Session parameter of the generated test closure.
+    fn session_param(&self) -> proc_macro2::TokenStream {
+        match self.session {
+            SessionArg::None => quote! { _ },
+            SessionArg::ByRef | SessionArg::ByValue => quote! { session },
+        }
+    }
+
+    /// Test closure, given session, returning async block invoking the test function
+    /// with the result passed through `wrap`.
+    fn call(
+        &self,
+        ident: &Ident,
+        case_args: &[syn::Expr],
+        wrap: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let session_param = self.session_param();
+        let session_arg = match self.session {
+            SessionArg::None => None,
+            SessionArg::ByRef => Some(quote! { &session }),
+            SessionArg::ByValue => Some(quote! { session }),
+        };
+        let args = session_arg
+            .into_iter()
+            .chain(case_args.iter().map(|arg| quote! { #arg }));
+        let await_call = if self.is_async {
+            quote! { .await }
+        } else {
+            quote! {}
+        };
+
+        quote! {
+            |#session_param: gear_test_runtime::SessionData| async move {
+                #wrap(#ident(#(#args),*) #await_call)
+            }
+        }
+    }
+}
+
+/// Declares on-chain test.
+///
+/// Test is a function, `async` or not, taking `&SessionData`, `SessionData` or nothing.
+/// It can return `()` or `Result<(), E>` where `E: Debug`; `Err` fails the test.
+///
+/// Supported arguments:
+/// - `should_panic` or `should_panic(expected = "substring")`: test passes only if it panics
+///   (with panic message containing `substring`, needs `panic-message` feature of
+///   `gear-test-runtime`).
+/// - `ignore` or `ignore = "reason"`: test runs only when ignored tests are requested.
+/// - `timeout_blocks = N`: test fails with timeout if it does not finish in `N` blocks.
+/// - `retries = N`: failed test is run again up to `N` times, for tests depending on block timing.
+/// - `case(label, args...)`, repeated: registers separate `test_name::label` test for each case,
+///   passing `args` to test parameters following the session.
 #[proc_macro_attribute]
-pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut attrs = TestAttrs::default();
+    let attrs_parser = syn::meta::parser(|meta| attrs.parse(meta));
+    syn::parse_macro_input!(attr with attrs_parser);
+
+    let function = syn::parse_macro_input!(item as syn::ItemFn);
+    let (signature, output) = match TestSignature::parse(&function.sig, &attrs.cases)
+        .and_then(|signature| Ok((signature, test_output(&function.sig, &attrs)?)))
+    {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into_compile_error().into(),
+    };
+    // errors about unsupported return type should point to the return type
+    let into_test_result = quote_spanned! { output.unwrap_or_else(Span::call_site)=>
+        gear_test_runtime::IntoTestResult::into_test_result
+    };
+    let ident = &function.sig.ident;
+    let should_panic = attrs.should_panic();
+    let ignore = attrs.ignore();
+    let timeout_blocks = attrs.timeout_blocks();
+    let retries = attrs.retries();
+
+    let export = |case: Option<&TestCase>| {
+        let (extern_ident, path, call) = match case {
+            None => (
+                Ident::new(&format!("__gtest_{}", ident), Span::call_site()),
+                quote! { module_path!(), "::", stringify!(#ident) },
+                signature.call(ident, &[], &into_test_result),
+            ),
+            Some(TestCase { label, args }) => (
+                Ident::new(&format!("__gtest_{}__{}", ident, label), Span::call_site()),
+                quote! { module_path!(), "::", stringify!(#ident), "::", stringify!(#label) },
+                signature.call(ident, args, &into_test_result),
+            ),
+        };
+
+        // Export is named after full test path, so that tests with the same name
+        // in different modules do not clash.
+        quote! {
+            #[export_name = concat!("__gtest_", #path)]
+            pub unsafe extern "C" fn #extern_ident() {
+                let test_future = gear_test_runtime::ContextFuture::new(
+                    #call,
+                    concat!(#path),
+                )
+                .with_should_panic(#should_panic)
+                #ignore
+                #timeout_blocks
+                #retries;
+
+                gear_test_runtime::CONTEXT_FUTURES.push(test_future);
+            }
+        }
+    };
+
+    let exports = if attrs.cases.is_empty() {
+        vec![export(None)]
+    } else {
+        attrs.cases.iter().map(Some).map(export).collect()
+    };
+
+    quote! {
+        #function
+
+        #(#exports)*
+    }
+    .into()
+}
+
+/// Setup/teardown hook, see [`before_all`].
+fn hook(kind: proc_macro2::TokenStream, attr: TokenStream, item: TokenStream) -> TokenStream {
+    if let Some(token) = proc_macro2::TokenStream::from(attr).into_iter().next() {
+        return syn::Error::new(token.span(), "hook attribute takes no arguments")
+            .into_compile_error()
+            .into();
+    }
+
     let function = syn::parse_macro_input!(item as syn::ItemFn);
+    let signature = match TestSignature::parse(&function.sig, &[]) {
+        Ok(signature) => signature,
+        Err(e) => return e.into_compile_error().into(),
+    };
     let ident = &function.sig.ident;
-    let extern_ident = Ident::new(&format!("test_{}", ident), Span::call_site());
+    let wrap = match function.sig.output {
+        syn::ReturnType::Type(_, ref ty) if is_result(ty) => {
+            quote! { gear_test_runtime::HookResult::from_result }
+        }
+        _ => quote! { gear_test_runtime::HookResult::from_fixture },
+    };
+    let call = signature.call(ident, &[], &wrap);
+    let extern_ident = Ident::new(&format!("__gtest_{}", ident), Span::call_site());
 
     quote! {
         #function
 
-        #[no_mangle]
+        #[export_name = concat!("__gtest_", module_path!(), "::", stringify!(#ident))]
         pub unsafe extern "C" fn #extern_ident() {
-            let test_future = gear_test_runtime::ContextFuture::new(
-                async {
-                    let session = gear_test_runtime::active_session();
-                    #ident(&session).await;
-                },
+            let hook_future = gear_test_runtime::ContextFuture::hook(
+                gear_test_runtime::HookKind::#kind,
+                #call,
                 concat!(module_path!(), "::", stringify!(#ident)),
             );
 
-            gear_test_runtime::CONTEXT_FUTURES.push(test_future);
+            gear_test_runtime::CONTEXT_FUTURES.push(hook_future);
         }
     }
     .into()
 }
+
+/// Declares hook running once per session before all tests.
+///
+/// Hook is a function of the same shape as test, returning `()`, any `Encode` value or
+/// `Result<T, E>` where `E: Debug`; `Err` fails the hook. Value returned by `before_all` and
+/// `before_each` hooks is available to tests and later hooks as
+/// `session.fixture::<T>("hook_function_name")`, or by full hook path if the name is ambiguous.
+///
+/// If hook fails, tests are not run and reported as skipped, failure is reported for the hook.
+#[proc_macro_attribute]
+pub fn before_all(attr: TokenStream, item: TokenStream) -> TokenStream {
+    hook(quote! { BeforeAll }, attr, item)
+}
+
+/// Declares hook running before each test of its module (and submodules), see [`before_all`].
+///
+/// If hook fails, test is not run and hook failure is reported for the test.
+#[proc_macro_attribute]
+pub fn before_each(attr: TokenStream, item: TokenStream) -> TokenStream {
+    hook(quote! { BeforeEach }, attr, item)
+}
+
+/// Declares hook running after each test of its module (and submodules), see [`before_all`].
+///
+/// If hook fails after passed test, hook failure is reported for the test instead of success.
+#[proc_macro_attribute]
+pub fn after_each(attr: TokenStream, item: TokenStream) -> TokenStream {
+    hook(quote! { AfterEach }, attr, item)
+}
+
+/// Declares hook running once per session after all tests, see [`before_all`].
+#[proc_macro_attribute]
+pub fn after_all(attr: TokenStream, item: TokenStream) -> TokenStream {
+    hook(quote! { AfterAll }, attr, item)
+}
+
+fn is_async_main(function: &syn::ItemFn) -> bool {
+    function.attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "async_main")
+    })
+}
+
+/// Whether function is exported as `handle` entry point.
+fn is_handle_export(function: &syn::ItemFn) -> bool {
+    function.attrs.iter().any(|attr| match attr.meta {
+        syn::Meta::Path(ref path) => path.is_ident("no_mangle") && function.sig.ident == "handle",
+        syn::Meta::NameValue(ref name_value) => {
+            name_value.path.is_ident("export_name")
+                && matches!(
+                    name_value.value,
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(ref name),
+                        ..
+                    }) if name.value() == "handle"
+                )
+        }
+        _ => false,
+    })
+}
+
+fn handle_export_error(function: &syn::ItemFn) -> syn::Error {
+    syn::Error::new_spanned(
+        &function.sig,
+        "custom `handle` entry point is not supported by test runtime, \
+        as it is replaced in test binary; use `#[gstd::async_main]` instead",
+    )
+}
+
+/// Collects all functions of the module, including nested inline modules.
+fn module_functions<'a>(items: &'a [syn::Item], functions: &mut Vec<&'a syn::ItemFn>) {
+    for item in items {
+        match item {
+            syn::Item::Fn(function) => functions.push(function),
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => module_functions(items, functions),
+            _ => {}
+        }
+    }
+}
+
+fn check_module(module: &syn::ItemMod) -> syn::Result<()> {
+    let Some((_, ref items)) = module.content else {
+        return Err(syn::Error::new_spanned(
+            module,
+            "`runtime` attribute can only be applied to inline module",
+        ));
+    };
+
+    let mut functions = Vec::new();
+    module_functions(items, &mut functions);
+
+    let mut errors = functions
+        .iter()
+        .filter(|function| is_handle_export(function))
+        .map(|function| handle_export_error(function));
+
+    if let Some(mut error) = errors.next() {
+        errors.for_each(|other| error.combine(other));
+        return Err(error);
+    }
+
+    if !functions.iter().any(|function| is_async_main(function)) {
+        return Err(syn::Error::new_spanned(
+            &module.ident,
+            "test runtime requires `#[gstd::async_main]` entry point in this module",
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_function(function: &syn::ItemFn) -> syn::Result<()> {
+    if is_handle_export(function) {
+        return Err(handle_export_error(function));
+    }
+
+    if !is_async_main(function) {
+        return Err(syn::Error::new_spanned(
+            &function.sig,
+            "test runtime requires `#[gstd::async_main]` entry point; \
+            put `runtime` attribute above `#[gstd::async_main]`",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Wires test runtime into the program.
+///
+/// Should be applied either to the `#[gstd::async_main]` entry point (above `gstd::async_main`
+/// attribute) or to the inline module containing it. Programs with custom `handle` are rejected,
+/// since test binary replaces `handle` with the test runner. Program `init`, if any, is replaced
+/// too, with the one configuring who can run tests.
+///
+/// Only the annotated function or module is checked: custom `handle` exported from any other
+/// module of the crate is not detected, and conflicts with the test runner entry point. Keep all
+/// entry points of the program in the annotated module.
+#[proc_macro_attribute]
+pub fn runtime(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::TokenStream::from(attr)
+                .into_iter()
+                .next()
+                .map_or_else(Span::call_site, |token| token.span()),
+            "`runtime` attribute takes no arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let item = syn::parse_macro_input!(item as syn::Item);
+    let checked = match item {
+        syn::Item::Mod(ref module) => check_module(module),
+        syn::Item::Fn(ref function) => check_function(function),
+        _ => Err(syn::Error::new_spanned(
+            &item,
+            "`runtime` attribute can only be applied to `#[gstd::async_main]` function or module",
+        )),
+    };
+
+    if let Err(e) = checked {
+        return e.into_compile_error().into();
+    }
+
+    let run_tests = quote! {
+        #[export_name = "run_tests"]
+        pub unsafe extern "C" fn __gtest_run_tests(ptr: *const u8) {
+            gear_test_runtime::run_tests(ptr)
+        }
+
+        #[export_name = "init_tests"]
+        pub unsafe extern "C" fn __gtest_init_tests() {
+            gear_test_runtime::init_tests()
+        }
+    };
+
+    match item {
+        syn::Item::Mod(mut module) => {
+            if let Some((_, ref mut items)) = module.content {
+                items.push(syn::Item::Verbatim(run_tests));
+            }
+            quote! { #module }.into()
+        }
+        item => quote! {
+            #item
+
+            #run_tests
+        }
+        .into(),
+    }
+}
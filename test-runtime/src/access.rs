@@ -0,0 +1,39 @@
+#![allow(static_mut_refs)]
+
+use super::Access;
+use codec::Decode;
+use gstd::{exec, msg, prelude::*, ActorId};
+
+// written only once, by init message
+static mut ACCESS: Option<Access> = None;
+
+/// Configures who can run tests, from the init payload.
+///
+/// Empty payload allows only the deployer.
+pub(crate) fn init() {
+    let payload = msg::load_bytes().expect("Failed to load init payload");
+
+    let access = if payload.is_empty() {
+        Access::Only(vec![msg::source()])
+    } else {
+        Access::decode(&mut &payload[..]).expect("Failed to decode test program access config")
+    };
+
+    unsafe { ACCESS = Some(access) };
+}
+
+/// Whether `actor` can start test sessions.
+///
+/// Nobody can, if test program was not initialized.
+pub(crate) fn can_run_tests(actor: ActorId) -> bool {
+    match unsafe { ACCESS.as_ref() } {
+        Some(Access::Anyone) => true,
+        Some(Access::Only(allowed)) => allowed.contains(&actor),
+        None => false,
+    }
+}
+
+/// Whether `actor` is this program, which is the only one to execute tests in isolation.
+pub(crate) fn can_execute(actor: ActorId) -> bool {
+    actor == exec::program_id()
+}
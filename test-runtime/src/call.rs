@@ -0,0 +1,84 @@
+use codec::{Decode, DecodeAll, Encode};
+use core::fmt;
+use gstd::{msg, prelude::*, ActorId};
+
+use crate::SessionData;
+
+/// Failure of the typed call.
+#[derive(Debug)]
+pub enum CallError {
+    /// Request could not be sent.
+    Send(gstd::errors::Error),
+    /// Error reply (callee panicked, ran out of gas, etc.).
+    Reply(gstd::errors::Error),
+    /// Reply is not a valid encoding of the expected response type.
+    Decode {
+        response_type: &'static str,
+        reply: Vec<u8>,
+        error: codec::Error,
+    },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::Send(e) => write!(f, "failed to send request: {}", e),
+            CallError::Reply(e) => write!(f, "error reply: {}", e),
+            CallError::Decode {
+                response_type,
+                reply,
+                error,
+            } => write!(
+                f,
+                "failed to decode reply as `{}`: {}, reply bytes: {:?}",
+                response_type, error, reply
+            ),
+        }
+    }
+}
+
+impl SessionData {
+    /// Sends encoded `request` to the `actor`, decoding its reply as `Resp`.
+    pub async fn call<Req: Encode, Resp: Decode>(
+        &self,
+        actor: ActorId,
+        request: Req,
+    ) -> Result<Resp, CallError> {
+        self.call_with_value(actor, request, 0).await
+    }
+
+    /// Same as [`call`](Self::call), transferring `value` with the request.
+    pub async fn call_with_value<Req: Encode, Resp: Decode>(
+        &self,
+        actor: ActorId,
+        request: Req,
+        value: u128,
+    ) -> Result<Resp, CallError> {
+        let reply = msg::send_for_reply(actor, request, value, 0).map_err(CallError::Send)?;
+
+        decode_reply(reply.await.map_err(CallError::Reply)?)
+    }
+
+    /// Same as [`call_with_value`](Self::call_with_value), with explicit `gas_limit`.
+    pub async fn call_with_gas<Req: Encode, Resp: Decode>(
+        &self,
+        actor: ActorId,
+        request: Req,
+        gas_limit: u64,
+        value: u128,
+    ) -> Result<Resp, CallError> {
+        let reply = msg::send_with_gas_for_reply(actor, request, gas_limit, value, 0)
+            .map_err(CallError::Send)?;
+
+        decode_reply(reply.await.map_err(CallError::Reply)?)
+    }
+}
+
+/// Decodes the whole reply as `Resp`, trailing bytes are an error too.
+fn decode_reply<Resp: Decode>(reply: Vec<u8>) -> Result<Resp, CallError> {
+    Resp::decode_all(&mut &reply[..]).map_err(|error| CallError::Decode {
+        response_type: core::any::type_name::<Resp>(),
+        reply,
+        error,
+    })
+}
@@ -0,0 +1,59 @@
+use codec::Encode;
+use core::fmt;
+use gstd::{prog::ProgramGenerator, ActorId, CodeId};
+
+use crate::SessionData;
+
+/// Failure of the testee deployment.
+#[derive(Debug)]
+pub enum DeployError {
+    /// Program creation message could not be sent.
+    Create(gstd::errors::Error),
+    /// Testee `init` failed (panicked, ran out of gas, etc.).
+    Init(gstd::errors::Error),
+}
+
+impl fmt::Display for DeployError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeployError::Create(e) => write!(f, "failed to create testee program: {}", e),
+            DeployError::Init(e) => write!(f, "testee init failed: {}", e),
+        }
+    }
+}
+
+impl SessionData {
+    /// Deploys new instance of the testee with encoded `init` payload.
+    ///
+    /// Waits for `init` to finish, so that init failure is reported right here.
+    pub async fn deploy<E: Encode>(&self, init: E) -> Result<ActorId, DeployError> {
+        self.deploy_with_value(init, 0).await
+    }
+
+    /// Same as [`deploy`](Self::deploy), transferring `value` to the new instance.
+    pub async fn deploy_with_value<E: Encode>(
+        &self,
+        init: E,
+        value: u128,
+    ) -> Result<ActorId, DeployError> {
+        self.deploy_code(self.testee(), init, value).await
+    }
+
+    /// Deploys program of any code, e.g. [dependency](Self::code) of the testee.
+    ///
+    /// Each instance gets unique salt, so the same code can be deployed many times
+    /// with the same payload.
+    pub async fn deploy_code<E: Encode>(
+        &self,
+        code_id: CodeId,
+        init: E,
+        value: u128,
+    ) -> Result<ActorId, DeployError> {
+        let (actor_id, _) = ProgramGenerator::create_program_for_reply(code_id, init, value, 0)
+            .map_err(DeployError::Create)?
+            .await
+            .map_err(DeployError::Init)?;
+
+        Ok(actor_id)
+    }
+}
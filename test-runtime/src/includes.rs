@@ -1,17 +1,119 @@
 #![allow(static_mut_refs)]
 
-use super::ControlSignal;
-use core::{future::Future, pin::Pin};
-use gstd::{msg, prelude::*};
+use super::{
+    ControlSignal, RunMode, RuntimeInfo, TestFilter, TestInfo, TestListing, TestOrder, TestUpdate,
+};
+use codec::{Decode, Encode};
+use core::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::TryStreamExt;
+use gstd::{exec, msg, prelude::*, MessageId};
 
-use crate::sessions;
+use crate::{
+    access, log,
+    sessions::{self, Fixtures, SessionData},
+    ControlError,
+};
 
-#[derive(Debug)]
+/// Outcome of the test which did not panic, replied by `WrapExecute`.
+#[derive(Debug, codec::Encode, codec::Decode)]
 pub enum TestResult {
     Ok,
     Fail(String),
 }
 
+/// `WrapExecute` reply of the test or hook which did not panic.
+#[derive(Debug, codec::Encode, codec::Decode)]
+struct ExecuteReply {
+    gas_burned: u64,
+    result: TestResult,
+    /// Encoded fixture of the hook, empty for test.
+    output: Vec<u8>,
+}
+
+impl ExecuteReply {
+    /// Reply which could not be decoded fails the test (or hook), not the whole session.
+    fn undecodable(error: codec::Error) -> Self {
+        ExecuteReply {
+            gas_burned: 0,
+            result: TestResult::Fail(format!("Failed to decode execute reply: {}", error)),
+            output: Vec::new(),
+        }
+    }
+}
+
+/// Counts gas burned while polling the inner future.
+///
+/// Each poll may happen in different message, so gas is summed up poll by poll, not including
+/// time the future spends waiting.
+struct GasMeter<F> {
+    fut: F,
+    gas_burned: u64,
+}
+
+impl<F: Future + Unpin> Future for GasMeter<F> {
+    type Output = (u64, F::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let gas_before = exec::gas_available();
+        let poll = Pin::new(&mut self.fut).poll(cx);
+        self.gas_burned += gas_before.saturating_sub(exec::gas_available());
+
+        poll.map(|output| (self.gas_burned, output))
+    }
+}
+
+/// Conversion of the test function output into [`TestResult`].
+pub trait IntoTestResult {
+    fn into_test_result(self) -> TestResult;
+}
+
+impl IntoTestResult for () {
+    fn into_test_result(self) -> TestResult {
+        TestResult::Ok
+    }
+}
+
+impl<E: fmt::Debug> IntoTestResult for Result<(), E> {
+    fn into_test_result(self) -> TestResult {
+        match self {
+            Ok(()) => TestResult::Ok,
+            Err(e) => TestResult::Fail(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Outcome of the hook which did not panic, along with its encoded fixture.
+pub struct HookResult {
+    result: TestResult,
+    fixture: Vec<u8>,
+}
+
+impl HookResult {
+    /// Hook returning the fixture as is.
+    pub fn from_fixture<T: Encode>(fixture: T) -> Self {
+        HookResult {
+            result: TestResult::Ok,
+            fixture: fixture.encode(),
+        }
+    }
+
+    /// Hook returning `Result`, `Err` fails the hook the same way as it fails test.
+    pub fn from_result<T: Encode, E: fmt::Debug>(result: Result<T, E>) -> Self {
+        match result {
+            Ok(fixture) => HookResult::from_fixture(fixture),
+            Err(e) => HookResult {
+                result: TestResult::Fail(format!("{:?}", e)),
+                fixture: Vec::new(),
+            },
+        }
+    }
+}
+
 unsafe fn read_tests(mut ptr: *const u8) -> Vec<unsafe extern "C" fn()> {
     let mut buf = [0u8; 4];
     buf.clone_from_slice(slice::from_raw_parts(ptr, 4));
@@ -31,29 +133,200 @@ unsafe fn read_tests(mut ptr: *const u8) -> Vec<unsafe extern "C" fn()> {
     result
 }
 
+/// Failure description of the `WrapExecute` reply error.
+///
+/// Error reply of the panicked test carries panic message along with its location (gstd
+/// `panic-location`), e.g. `assert_eq!` left and right values, with `panic-message` feature.
+fn error_message(e: &gstd::errors::Error) -> String {
+    match e {
+        gstd::errors::Error::ErrorReply(payload, _) if !payload.0.is_empty() => {
+            String::from_utf8_lossy(&payload.0).into_owned()
+        }
+        e => gstd::string::ToString::to_string(e),
+    }
+}
+
+/// Whether test is expected to panic, same as libtest `#[should_panic]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShouldPanic {
+    No,
+    Yes,
+    /// Panic message should contain this substring.
+    YesWithMessage(&'static str),
+}
+
+impl ShouldPanic {
+    /// Checks `WrapExecute` reply against the expectation.
+    ///
+    /// Returns failure hint if test outcome does not match.
+    fn check(&self, result: Result<TestResult, gstd::errors::Error>) -> Result<(), String> {
+        match (self, result) {
+            (_, Ok(TestResult::Fail(hint))) => Err(hint),
+            (ShouldPanic::No, Ok(TestResult::Ok)) => Ok(()),
+            (ShouldPanic::No, Err(e)) => Err(error_message(&e)),
+            (_, Ok(TestResult::Ok)) => Err(String::from("test did not panic as expected")),
+            (ShouldPanic::Yes, Err(_)) => Ok(()),
+            (ShouldPanic::YesWithMessage(expected), Err(e)) => {
+                let message = error_message(&e);
+                if message.contains(expected) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "panic did not contain expected string\n      panic message: `{}`,\n expected substring: `{}`",
+                        message, expected
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Setup/teardown hook kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// Runs once per session before all tests.
+    BeforeAll,
+    /// Runs before each test.
+    BeforeEach,
+    /// Runs after each test.
+    AfterEach,
+    /// Runs once per session after all tests.
+    AfterAll,
+}
+
+impl HookKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookKind::BeforeAll => "before_all",
+            HookKind::BeforeEach => "before_each",
+            HookKind::AfterEach => "after_each",
+            HookKind::AfterAll => "after_all",
+        }
+    }
+}
+
+/// Test (or hook) description, available without running the test.
+#[derive(Debug, Clone, Copy)]
+pub struct TestDesc {
+    pub name: &'static str,
+    /// `Some` if this is a setup/teardown hook rather than a test.
+    pub hook: Option<HookKind>,
+    pub should_panic: ShouldPanic,
+    pub ignore: bool,
+    pub ignore_message: Option<&'static str>,
+    /// Overrides session default timeout.
+    pub timeout_blocks: Option<u32>,
+    /// Overrides session default number of retries.
+    pub retries: Option<u32>,
+}
+
+impl TestDesc {
+    /// Whether `before_each` / `after_each` hook runs around the test, that is test is declared
+    /// in the module of the hook or its submodules.
+    fn applies_to(&self, test_name: &str) -> bool {
+        match self.name.rsplit_once("::") {
+            Some((module, _)) => test_name
+                .strip_prefix(module)
+                .is_some_and(|rest| rest.starts_with("::")),
+            None => true,
+        }
+    }
+}
+
+/// Test body, given the session it runs in, resolves to its result and encoded fixture (if hook).
+type TestFn =
+    Box<dyn FnOnce(SessionData) -> Pin<Box<dyn Future<Output = (TestResult, Vec<u8>)> + 'static>>>;
+
 pub struct ContextFuture {
-    fut: Pin<Box<dyn Future<Output = ()> + 'static>>,
-    name: &'static str,
+    test_fn: TestFn,
+    desc: TestDesc,
 }
 
 impl ContextFuture {
-    pub fn new(
-        fut: impl future::Future<Output = ()> + 'static + gstd::Send,
-        name: &'static str,
-    ) -> Self {
+    pub fn new<F, Fut>(test_fn: F, name: &'static str) -> Self
+    where
+        F: FnOnce(SessionData) -> Fut + 'static,
+        Fut: future::Future<Output = TestResult> + 'static + gstd::Send,
+    {
         use futures::FutureExt;
         ContextFuture {
-            fut: fut.boxed(),
-            name,
+            test_fn: Box::new(|session| {
+                test_fn(session)
+                    .map(|test_result| (test_result, Vec::new()))
+                    .boxed()
+            }),
+            desc: TestDesc {
+                name,
+                hook: None,
+                should_panic: ShouldPanic::No,
+                ignore: false,
+                ignore_message: None,
+                timeout_blocks: None,
+                retries: None,
+            },
         }
     }
 
+    /// Setup/teardown hook, fixture of `before_*` hooks is available to tests.
+    pub fn hook<F, Fut>(kind: HookKind, hook_fn: F, name: &'static str) -> Self
+    where
+        F: FnOnce(SessionData) -> Fut + 'static,
+        Fut: future::Future<Output = HookResult> + 'static + gstd::Send,
+    {
+        use futures::FutureExt;
+        ContextFuture {
+            test_fn: Box::new(|session| {
+                hook_fn(session)
+                    .map(|hook_result| (hook_result.result, hook_result.fixture))
+                    .boxed()
+            }),
+            desc: TestDesc {
+                name,
+                hook: Some(kind),
+                should_panic: ShouldPanic::No,
+                ignore: false,
+                ignore_message: None,
+                timeout_blocks: None,
+                retries: None,
+            },
+        }
+    }
+
+    pub fn with_should_panic(mut self, should_panic: ShouldPanic) -> Self {
+        self.desc.should_panic = should_panic;
+        self
+    }
+
+    pub fn with_ignore(mut self, reason: Option<&'static str>) -> Self {
+        self.desc.ignore = true;
+        self.desc.ignore_message = reason;
+        self
+    }
+
+    pub fn with_timeout_blocks(mut self, blocks: u32) -> Self {
+        self.desc.timeout_blocks = Some(blocks);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.desc.retries = Some(retries);
+        self
+    }
+
     pub fn name(&self) -> &'static str {
-        self.name
+        self.desc.name
     }
 
-    pub fn into_future(self) -> Pin<Box<dyn Future<Output = ()> + 'static>> {
-        self.fut
+    pub fn desc(&self) -> TestDesc {
+        self.desc
+    }
+
+    /// Test future running in the `session`, owned by it.
+    pub fn into_future(
+        self,
+        session: SessionData,
+    ) -> Pin<Box<dyn Future<Output = (TestResult, Vec<u8>)> + 'static>> {
+        (self.test_fn)(session)
     }
 }
 
@@ -69,7 +342,7 @@ fn extract_test_context(ptr: *const u8, index: u32) -> ContextFuture {
     }
 }
 
-fn extract_test_names(ptr: *const u8) -> Vec<&'static str> {
+fn extract_test_descs(ptr: *const u8) -> Vec<TestDesc> {
     unsafe {
         let tests = read_tests(ptr);
         for test in tests {
@@ -77,7 +350,7 @@ fn extract_test_names(ptr: *const u8) -> Vec<&'static str> {
         }
         core::mem::replace(&mut CONTEXT_FUTURES, Vec::new())
             .into_iter()
-            .map(|con_fut| con_fut.name())
+            .map(|con_fut| con_fut.desc())
             .collect()
     }
 }
@@ -85,65 +358,451 @@ fn extract_test_names(ptr: *const u8) -> Vec<&'static str> {
 // thread-local-like variable for run_tests workflow (synchronously populating one big future)
 pub static mut CONTEXT_FUTURES: Vec<ContextFuture> = Vec::new();
 
-pub fn run_tests(ptr: *const u8) {
-    // at the moment, just runs all tests
+/// Session was aborted or expired while waiting for `WrapExecute` reply.
+///
+/// Nothing is reported for the session after that.
+struct Aborted;
+
+/// Options of the session, as given in `ControlSignal::Test`.
+struct RunOptions {
+    filter: TestFilter,
+    run_mode: RunMode,
+    timeout_blocks: Option<u32>,
+    max_concurrency: u32,
+    fail_fast: bool,
+    retries: u32,
+    order: TestOrder,
+}
+
+/// Executes test or hook by index in a separate message, so that panic is caught.
+///
+/// Without timeout, waits for the reply indefinitely. Lines logged by the execution are appended
+/// to `logs`, whatever its outcome.
+async fn execute(
+    session_id: MessageId,
+    index: u32,
+    fixtures: &Fixtures,
+    logs: &mut Vec<String>,
+    timeout_blocks: Option<u32>,
+) -> Result<Result<ExecuteReply, gstd::errors::Error>, Aborted> {
+    let reply = msg::send_for_reply(
+        gstd::exec::program_id(),
+        ControlSignal::WrapExecute(session_id, index, fixtures.clone()),
+        0,
+        0,
+    )
+    .expect("Failed to send message");
+    // lines are logged by `WrapExecute` message id
+    let execution = reply.waiting_reply_to;
+
+    let payload = match timeout_blocks {
+        Some(blocks) => {
+            reply
+                .up_to(Some(blocks))
+                .expect("Failed to set reply timeout")
+                .await
+        }
+        None => reply.await,
+    };
+    logs.append(&mut log::take(&execution));
+
+    // late reply of aborted session
+    if !sessions::is_active(&session_id).await {
+        return Err(Aborted);
+    }
+
+    Ok(payload.map(|payload| {
+        ExecuteReply::decode(&mut &payload[..]).unwrap_or_else(ExecuteReply::undecodable)
+    }))
+}
+
+/// Runs all hooks of the kind in declaration order, collecting fixtures by full hook path.
+///
+/// `before_each` and `after_each` hooks run only if they apply to the test, given as `test_index`.
+/// Stops at the first failed hook, returning its index and failure hint.
+async fn run_hooks(
+    kind: HookKind,
+    descs: &[TestDesc],
+    test_index: Option<u32>,
+    session_id: MessageId,
+    fixtures: &mut Fixtures,
+    logs: &mut Vec<String>,
+    timeout_blocks: Option<u32>,
+) -> Result<Result<(), (u32, String)>, Aborted> {
+    for (index, desc) in descs.iter().enumerate() {
+        let applies = match test_index {
+            Some(test_index) => desc.applies_to(descs[test_index as usize].name),
+            None => true,
+        };
+        if desc.hook != Some(kind) || !applies {
+            continue;
+        }
+
+        let failure =
+            match execute(session_id, index as u32, fixtures, logs, timeout_blocks).await? {
+                Ok(reply) => match reply.result {
+                    TestResult::Ok => {
+                        if matches!(kind, HookKind::BeforeAll | HookKind::BeforeEach) {
+                            sessions::set_fixture(fixtures, desc.name, reply.output);
+                        }
+                        continue;
+                    }
+                    TestResult::Fail(hint) => hint,
+                },
+                Err(e) => error_message(&e),
+            };
+
+        return Ok(Err((
+            index as u32,
+            format!("{} hook `{}` failed: {}", kind.name(), desc.name, failure),
+        )));
+    }
+
+    Ok(Ok(()))
+}
+
+/// Runs single attempt of the test with `before_each` and `after_each` hooks around it.
+///
+/// Returns the final update of the attempt, it passed if that is `Success`, along with lines
+/// logged by the test and its hooks.
+async fn run_attempt(
+    descs: &[TestDesc],
+    test_index: u32,
+    session_id: MessageId,
+    fixtures: &Fixtures,
+    default_timeout_blocks: Option<u32>,
+) -> Result<(TestUpdate, Vec<String>), Aborted> {
+    let test_desc = descs[test_index as usize];
+    let mut fixtures = fixtures.clone();
+    let mut logs = Vec::new();
+
+    if let Err((_, hint)) = run_hooks(
+        HookKind::BeforeEach,
+        descs,
+        Some(test_index),
+        session_id,
+        &mut fixtures,
+        &mut logs,
+        default_timeout_blocks,
+    )
+    .await?
+    {
+        return Ok((TestUpdate::HookFailed(hint), logs));
+    }
+
+    let timeout_blocks = test_desc.timeout_blocks.or(default_timeout_blocks);
+    let test_result = execute(session_id, test_index, &fixtures, &mut logs, timeout_blocks).await?;
+
+    let update = if let (Err(gstd::errors::Error::Timeout(..)), Some(blocks)) =
+        (&test_result, timeout_blocks)
+    {
+        TestUpdate::Timeout(blocks)
+    } else {
+        // panicked test has no reply to report gas with
+        let gas_burned = test_result.as_ref().ok().map(|reply| reply.gas_burned);
+        let test_result = test_result.map(|reply| reply.result);
+
+        match test_desc.should_panic.check(test_result) {
+            Ok(()) => TestUpdate::Success(gas_burned),
+            Err(hint) => TestUpdate::Fail(hint, gas_burned),
+        }
+    };
 
+    let after_each = run_hooks(
+        HookKind::AfterEach,
+        descs,
+        Some(test_index),
+        session_id,
+        &mut fixtures,
+        &mut logs,
+        default_timeout_blocks,
+    )
+    .await?;
+
+    // single final update, with the first failure
+    let update = match (update, after_each) {
+        (update, Ok(())) => update,
+        (TestUpdate::Success(_), Err((_, hint))) => TestUpdate::HookFailed(hint),
+        (TestUpdate::Fail(hint, gas_burned), Err((_, hook_hint))) => {
+            TestUpdate::Fail(format!("{}\n{}", hint, hook_hint), gas_burned)
+        }
+        (update, Err(_)) => update,
+    };
+
+    Ok((update, logs))
+}
+
+/// Failure description of the attempt to run test again after.
+fn failure_hint(update: &TestUpdate) -> String {
+    match update {
+        TestUpdate::Fail(hint, _) | TestUpdate::HookFailed(hint) => hint.clone(),
+        TestUpdate::Timeout(blocks) => format!("test did not finish in {} blocks", blocks),
+        _ => String::new(),
+    }
+}
+
+/// Runs single test, running it again up to `retries` times while it fails.
+///
+/// Returns whether test passed (in any attempt), along with its hooks.
+async fn run_test(
+    descs: &[TestDesc],
+    test_index: u32,
+    session_id: MessageId,
+    session: &SessionData,
+    fixtures: &Fixtures,
+    options: &RunOptions,
+) -> Result<bool, Aborted> {
+    let test_desc = descs[test_index as usize];
+    let test_name = test_desc.name;
+    let retries = test_desc.retries.unwrap_or(options.retries);
+
+    session.test_start(test_index, test_name);
+
+    let mut attempt = 1;
+    loop {
+        let (update, logs) = run_attempt(
+            descs,
+            test_index,
+            session_id,
+            fixtures,
+            options.timeout_blocks,
+        )
+        .await?;
+        let passed = matches!(update, TestUpdate::Success(_));
+        // failed hooks are not the test being flaky
+        let retry = matches!(update, TestUpdate::Fail(..) | TestUpdate::Timeout(_));
+
+        if !retry || attempt > retries {
+            session.test_update(test_index, test_name, update, logs);
+            return Ok(passed);
+        }
+
+        let hint = failure_hint(&update);
+        session.test_retry(test_index, test_name, attempt, hint, logs);
+        attempt += 1;
+    }
+}
+
+/// Runs all selected tests of the session with `before_all` and `after_all` hooks around them.
+async fn run_session(
+    test_descs: &[TestDesc],
+    session_id: MessageId,
+    session: &SessionData,
+    options: RunOptions,
+) -> Result<(), Aborted> {
+    let mut selected = Vec::new();
+    for (test_index, test_desc) in test_descs.iter().enumerate() {
+        let test_index = test_index as u32;
+        let test_name = test_desc.name;
+        if test_desc.hook.is_some() || !options.filter.matches(test_name) {
+            continue;
+        }
+
+        match (options.run_mode, test_desc.ignore) {
+            (RunMode::Normal, true) => {
+                session.test_ignored(
+                    test_index,
+                    test_name,
+                    test_desc.ignore_message.map(String::from),
+                );
+                continue;
+            }
+            (RunMode::IgnoredOnly, false) => continue,
+            _ => {}
+        }
+
+        selected.push(test_index);
+    }
+    options.order.apply(&mut selected);
+
+    let mut fixtures = Fixtures::new();
+    let mut logs = Vec::new();
+
+    match run_hooks(
+        HookKind::BeforeAll,
+        test_descs,
+        None,
+        session_id,
+        &mut fixtures,
+        &mut logs,
+        options.timeout_blocks,
+    )
+    .await?
+    {
+        Ok(()) => {
+            // set by the first failed test in fail-fast mode
+            let stopped = Cell::new(false);
+
+            // up to `max_concurrency` tests wait for their `WrapExecute` replies at once
+            futures::stream::iter(selected.iter().copied().map(Ok))
+                .try_for_each_concurrent(options.max_concurrency.max(1) as usize, |test_index| {
+                    let stopped = &stopped;
+                    let fixtures = &fixtures;
+                    let options = &options;
+                    async move {
+                        if stopped.get() {
+                            session.test_skipped(test_index, test_descs[test_index as usize].name);
+                            return Ok(());
+                        }
+
+                        let passed = run_test(
+                            test_descs, test_index, session_id, session, fixtures, options,
+                        )
+                        .await?;
+                        if !passed && options.fail_fast {
+                            stopped.set(true);
+                        }
+
+                        Ok(())
+                    }
+                })
+                .await?;
+        }
+        Err((hook_index, hint)) => {
+            let name = test_descs[hook_index as usize].name;
+            session.test_hook_failed(hook_index, name, hint, logs);
+            for test_index in selected {
+                session.test_skipped(test_index, test_descs[test_index as usize].name);
+            }
+        }
+    }
+
+    let mut logs = Vec::new();
+    if let Err((hook_index, hint)) = run_hooks(
+        HookKind::AfterAll,
+        test_descs,
+        None,
+        session_id,
+        &mut fixtures,
+        &mut logs,
+        options.timeout_blocks,
+    )
+    .await?
+    {
+        let name = test_descs[hook_index as usize].name;
+        session.test_hook_failed(hook_index, name, hint, logs);
+    }
+
+    Ok(())
+}
+
+pub fn run_tests(ptr: *const u8) {
     gstd::message_loop(async move {
         // invoke all declared tests..
-        let signal = ControlSignal::current();
+        let signal = match ControlSignal::current() {
+            Ok(signal) => signal,
+            Err(e) => {
+                msg::reply(e, 0).expect("Failed to reply");
+                return;
+            }
+        };
+
+        let authorized = match signal {
+            ControlSignal::Test { .. } | ControlSignal::Abort(..) => {
+                access::can_run_tests(msg::source())
+            }
+            ControlSignal::WrapExecute(..) => access::can_execute(msg::source()),
+            ControlSignal::Hello | ControlSignal::List { .. } | ControlSignal::Sessions => true,
+        };
+        if !authorized {
+            msg::reply(ControlError::Unauthorized(msg::source()), 0).expect("Failed to reply");
+            return;
+        }
+
         match signal {
+            ControlSignal::Hello => {
+                msg::reply(RuntimeInfo::current(), 0).expect("Failed to reply");
+            }
             ControlSignal::Test {
                 code_hash,
+                codes,
                 control_bus,
+                filter,
+                run_mode,
+                timeout_blocks,
+                expiry_blocks,
+                max_concurrency,
+                fail_fast,
+                retries,
+                order,
             } => {
-                let me = gstd::exec::program_id();
-                let (session_id, active_session) =
-                    sessions::new_session(code_hash, control_bus).await;
-
-                let test_names = extract_test_names(ptr);
-                let test_count = test_names.len() as u32;
-
-                for test_index in 0..test_count {
-                    // running tests synchronously
-
-                    let test_name = test_names[test_index as usize];
-                    active_session.test_start(test_index, test_name);
-
-                    let test_result = msg::send_for_reply(
-                        me,
-                        ControlSignal::WrapExecute(session_id.clone(), test_index as u32),
-                        0,
-                        0,
-                    )
-                    .expect("Failed to send message")
-                    .await;
-
-                    match test_result {
-                        Ok(_) => {
-                            active_session.test_success(test_index, test_name);
-                        }
-                        Err(e) => {
-                            active_session.test_fail(
-                                test_index,
-                                test_name,
-                                gstd::string::ToString::to_string(&e),
-                            );
-                        }
+                let (session_id, session) =
+                    sessions::new_session(code_hash, codes, control_bus, expiry_blocks).await;
+
+                let test_descs = extract_test_descs(ptr);
+                let options = RunOptions {
+                    filter,
+                    run_mode,
+                    timeout_blocks,
+                    max_concurrency,
+                    fail_fast,
+                    retries,
+                    order,
+                };
+
+                match run_session(&test_descs, session_id, &session, options).await {
+                    Ok(()) => {
+                        sessions::drop_session(&session_id).await;
+                        msg::reply((), 0).expect("Failed to reply");
+                    }
+                    Err(Aborted) => {
+                        msg::reply(ControlError::SessionAborted(session_id), 0)
+                            .expect("Failed to reply");
                     }
                 }
+            }
+            ControlSignal::List { filter, run_mode } => {
+                let listing = extract_test_descs(ptr)
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, desc)| desc.hook.is_none() && filter.matches(desc.name))
+                    .filter(|(_, desc)| run_mode != RunMode::IgnoredOnly || desc.ignore)
+                    .map(|(index, desc)| TestListing {
+                        test_info: TestInfo {
+                            index: index as u32,
+                            name: desc.name.to_string(),
+                        },
+                        ignore: desc.ignore,
+                        should_panic: desc.should_panic != ShouldPanic::No,
+                        timeout_blocks: desc.timeout_blocks,
+                        retries: desc.retries,
+                    })
+                    .collect::<Vec<_>>();
 
-                sessions::drop_session(&session_id).await;
-
-                msg::reply((), 0).expect("Failed to reply");
+                msg::reply(listing, 0).expect("Failed to reply");
             }
-            ControlSignal::WrapExecute(session_id, test_index) => {
-                sessions::set_active_session(&session_id).await;
+            ControlSignal::WrapExecute(session_id, index, fixtures) => {
+                let Some(session) = sessions::execution_session(&session_id, fixtures).await else {
+                    msg::reply(ControlError::SessionNotFound(session_id), 0)
+                        .expect("Failed to reply");
+                    return;
+                };
 
                 // TODO: make sure it is obvious that only one is used?
-                let test_future = extract_test_context(ptr, test_index);
-                test_future.into_future().await;
+                let context_future = extract_test_context(ptr, index);
+                let (gas_burned, (result, output)) = GasMeter {
+                    fut: context_future.into_future(session),
+                    gas_burned: 0,
+                }
+                .await;
 
-                msg::reply((), 0).expect("Failed to reply");
+                let reply = ExecuteReply {
+                    gas_burned,
+                    result,
+                    output,
+                };
+                msg::reply(reply, 0).expect("Failed to reply");
+            }
+            ControlSignal::Abort(session_id) => {
+                if sessions::drop_session(&session_id).await {
+                    msg::reply((), 0).expect("Failed to reply");
+                } else {
+                    msg::reply(ControlError::SessionNotFound(session_id), 0)
+                        .expect("Failed to reply");
+                }
+            }
+            ControlSignal::Sessions => {
+                msg::reply(sessions::session_infos().await, 0).expect("Failed to reply");
             }
         };
     });
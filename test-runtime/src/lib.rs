@@ -1,40 +1,156 @@
 //! Support library to introduce test runtime to any gear program.
 //!
-//! Use #![gstd_test::runtime] for your crate root always. Decorate any function under
-//! test with #![gstd_test::test] to include it in the test list.
+//! Put #[gear_test_codegen::runtime] on your #[gstd::async_main] entry point (or the inline
+//! module containing it) always. Decorate any function under test with
+//! #[gear_test_codegen::test] to include it in the test list.
 //!
 //! In your build, include wasm-test-extractor::PreProcessor in build.rs
 //!
 //! Compatible only with gstd::async_main entry point, no custom `unsafe handle`-s please!
+//! The `runtime` attribute rejects those at compile time.
 
 #![no_std]
 
 use gstd::{prelude::*, ActorId, CodeId, MessageId};
 
+mod access;
+mod call;
+mod deploy;
 mod includes;
+mod log;
 mod sessions;
 
-pub use includes::{ContextFuture, TestResult, CONTEXT_FUTURES};
-pub use sessions::{active_session, SessionData};
+pub use call::CallError;
+pub use deploy::DeployError;
+pub use includes::{
+    ContextFuture, HookKind, HookResult, IntoTestResult, ShouldPanic, TestDesc, TestResult,
+    CONTEXT_FUTURES,
+};
+pub use sessions::{Codes, Fixtures, SessionData, SESSION_EXPIRY_BLOCKS};
+
+/// Version of `ControlSignal` / `ProgressSignal` wire format.
+///
+/// Bumped on any incompatible change of these, runner refuses to drive test program of another
+/// version.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional capabilities of this runtime, reported in `RuntimeInfo`.
+pub const FEATURES: &[&str] = &[
+    "list",
+    "timeout",
+    "concurrency",
+    "gas",
+    "sessions",
+    "retries",
+    "order",
+    "logs",
+];
+
+/// Reply to `ControlSignal::Hello`.
+///
+/// `protocol_version` goes first, so it decodes whatever the rest of the protocol is.
+#[derive(Debug, codec::Encode, codec::Decode)]
+pub struct RuntimeInfo {
+    pub protocol_version: u32,
+    /// `gear-test-runtime` crate version.
+    pub runtime_version: String,
+    pub features: Vec<String>,
+}
+
+impl RuntimeInfo {
+    pub fn current() -> Self {
+        RuntimeInfo {
+            protocol_version: PROTOCOL_VERSION,
+            runtime_version: env!("CARGO_PKG_VERSION").to_string(),
+            features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|supported| supported == feature)
+    }
+}
+
+/// Reply to control signal which could not be handled, instead of trapping.
+#[derive(Debug, codec::Encode, codec::Decode)]
+pub enum ControlError {
+    /// Payload is not a `ControlSignal` of this protocol version.
+    UndecodableSignal {
+        protocol_version: u32,
+        error: String,
+    },
+    /// Sender is not allowed to send this signal.
+    Unauthorized(ActorId),
+    /// Session does not exist, was aborted or expired.
+    SessionNotFound(MessageId),
+    /// Session was aborted or expired before all tests finished, replied to its `Test` signal.
+    SessionAborted(MessageId),
+}
+
+/// Running session, as replied for `ControlSignal::Sessions`.
+#[derive(Debug, codec::Encode, codec::Decode)]
+pub struct SessionInfo {
+    /// Id of the `Test` message which started the session.
+    pub id: MessageId,
+    pub code_hash: CodeId,
+    pub control_bus: ActorId,
+    /// Block height session started at.
+    pub started_at: u32,
+}
+
+/// Who can start test sessions, SCALE-encoded init payload of the test program.
+///
+/// Empty init payload allows only the deployer.
+#[derive(Debug, Clone, codec::Encode, codec::Decode)]
+pub enum Access {
+    Anyone,
+    Only(Vec<ActorId>),
+}
 
 #[derive(Debug, codec::Encode, codec::Decode)]
 pub enum TestUpdate {
     Start,
-    Success,
-    /// contains information about panic / error happened
-    Fail(String),
+    /// contains gas burned by the test, unless it panicked (as expected)
+    Success(Option<u64>),
+    /// contains information about panic (message and location) / error happened and gas burned,
+    /// unless it panicked
+    Fail(String, Option<u64>),
+    /// contains reason of ignoring, if any
+    Ignored(Option<String>),
+    /// setup/teardown hook failed, contains information about panic / error happened
+    HookFailed(String),
+    /// test did not finish in time, contains timeout in blocks
+    Timeout(u32),
+    /// test was not run, since `before_all` hook failed or session stopped at the first failure
+    Skipped,
+    /// test attempt failed and test is run again, contains number of the failed attempt
+    /// (starting from 1) and information about the failure
+    Retry(u32, String),
 }
 
-#[derive(Debug, codec::Encode, codec::Decode)]
+#[derive(Debug, Clone, codec::Encode, codec::Decode)]
 pub struct TestInfo {
     pub index: u32,
     pub name: String,
 }
 
+/// Test and its attributes, as replied for `ControlSignal::List`.
+#[derive(Debug, codec::Encode, codec::Decode)]
+pub struct TestListing {
+    pub test_info: TestInfo,
+    pub ignore: bool,
+    pub should_panic: bool,
+    pub timeout_blocks: Option<u32>,
+    pub retries: Option<u32>,
+}
+
 #[derive(Debug, codec::Encode, codec::Decode)]
 pub struct ProgressSignal {
     pub test_info: TestInfo,
     pub update: TestUpdate,
+    /// Lines logged by the test (attempt) and its hooks with `SessionData::log`, shipped with its
+    /// final update.
+    pub logs: Vec<String>,
 }
 
 impl ProgressSignal {
@@ -42,53 +158,253 @@ impl ProgressSignal {
         ProgressSignal {
             test_info: TestInfo { index, name },
             update: TestUpdate::Start,
+            logs: Vec::new(),
+        }
+    }
+
+    pub fn with_logs(mut self, logs: Vec<String>) -> Self {
+        self.logs = logs;
+        self
+    }
+
+    pub fn hook_failed(self, hint: String) -> Self {
+        let test_info = self.test_info;
+
+        ProgressSignal {
+            test_info,
+            logs: self.logs,
+            update: TestUpdate::HookFailed(hint),
+        }
+    }
+
+    pub fn retry(self, attempt: u32, hint: String) -> Self {
+        let test_info = self.test_info;
+
+        ProgressSignal {
+            test_info,
+            logs: self.logs,
+            update: TestUpdate::Retry(attempt, hint),
         }
     }
 
-    pub fn success(self) -> Self {
+    pub fn skipped(self) -> Self {
         let test_info = self.test_info;
 
         ProgressSignal {
             test_info,
-            update: TestUpdate::Success,
+            logs: self.logs,
+            update: TestUpdate::Skipped,
         }
     }
 
-    pub fn fail(self, hint: String) -> Self {
+    pub fn ignored(self, reason: Option<String>) -> Self {
         let test_info = self.test_info;
 
         ProgressSignal {
             test_info,
-            update: TestUpdate::Fail(hint),
+            logs: self.logs,
+            update: TestUpdate::Ignored(reason),
+        }
+    }
+}
+
+/// Test name filter, same semantics as libtest `<filter>`, `--exact` and `--skip` arguments.
+#[derive(Debug, Default, Clone, codec::Encode, codec::Decode)]
+pub struct TestFilter {
+    /// Run only tests matching any of these. Empty list matches all tests.
+    pub patterns: Vec<String>,
+    /// Skip tests matching any of these.
+    pub skip: Vec<String>,
+    /// Match whole test name instead of substring.
+    pub exact: bool,
+}
+
+impl TestFilter {
+    fn matches_pattern(&self, name: &str, pattern: &str) -> bool {
+        if self.exact {
+            name == pattern
+        } else {
+            name.contains(pattern)
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let included = self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| self.matches_pattern(name, pattern));
+
+        included
+            && !self
+                .skip
+                .iter()
+                .any(|pattern| self.matches_pattern(name, pattern))
+    }
+}
+
+/// Which tests to run with regard to `#[ignore]`, same as libtest `--ignored` / `--include-ignored`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, codec::Encode, codec::Decode)]
+pub enum RunMode {
+    /// Run only not ignored tests, report ignored ones as such.
+    #[default]
+    Normal,
+    /// Run only ignored tests.
+    IgnoredOnly,
+    /// Run both ignored and not ignored tests.
+    IncludeIgnored,
+}
+
+/// Order of running tests, to expose hidden dependencies between them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, codec::Encode, codec::Decode)]
+pub enum TestOrder {
+    /// Order of declaration, as tests are exported.
+    #[default]
+    Declaration,
+    /// Reverse order of declaration.
+    Reverse,
+    /// Pseudorandom order, the same for the same seed.
+    Shuffle(u64),
+}
+
+/// SplitMix64 step, good enough to shuffle tests and easy to reproduce anywhere.
+fn split_mix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl TestOrder {
+    /// Reorders tests given in declaration order.
+    pub fn apply<T>(&self, tests: &mut [T]) {
+        match *self {
+            TestOrder::Declaration => {}
+            TestOrder::Reverse => tests.reverse(),
+            TestOrder::Shuffle(seed) => {
+                // Fisher-Yates
+                let mut state = seed;
+                for i in (1..tests.len()).rev() {
+                    let j = (split_mix64(&mut state) % (i as u64 + 1)) as usize;
+                    tests.swap(i, j);
+                }
+            }
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffle_is_reproducible() {
+        let shuffled = |seed| {
+            let mut tests = (0..10).collect::<Vec<u32>>();
+            TestOrder::Shuffle(seed).apply(&mut tests);
+            tests
+        };
+
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), shuffled(43));
+
+        let mut sorted = shuffled(42);
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn reverse_is_reverse_declaration_order() {
+        let mut tests = [1, 2, 3];
+        TestOrder::Reverse.apply(&mut tests);
+        assert_eq!(tests, [3, 2, 1]);
+    }
+}
+
 #[derive(Debug, codec::Decode, codec::Encode)]
 pub enum ControlSignal {
-    /// Run all tests.
+    /// Handshake, replies with `RuntimeInfo`.
     ///
-    /// The only action can be called externally.
+    /// Should stay the first variant, so that it is understood by any protocol version.
+    Hello,
+
+    /// Run all tests matching the filter.
     ///
-    /// TODO: add test filter
+    /// Can be called externally, by actors allowed with `Access` at init.
     Test {
         code_hash: CodeId,
+        /// Named code ids of testee dependencies, available as `SessionData::code`.
+        codes: Codes,
         control_bus: ActorId,
+        filter: TestFilter,
+        run_mode: RunMode,
+        /// Default timeout for tests and hooks, waits indefinitely if `None`.
+        timeout_blocks: Option<u32>,
+        /// Session not finished in this many blocks is dropped, `SESSION_EXPIRY_BLOCKS` if `None`.
+        expiry_blocks: Option<u32>,
+        /// Number of tests run at once, `1` (or `0`) runs them one by one.
+        max_concurrency: u32,
+        /// Stop at the first failed test, reporting the rest as skipped.
+        fail_fast: bool,
+        /// Default number of times failed test is run again, overridden by
+        /// `#[test(retries = N)]`.
+        retries: u32,
+        /// Order of running selected tests.
+        order: TestOrder,
     },
 
-    /// Execute single test to try catch panic if any.
+    /// List tests matching the filter without running them, replies with `Vec<TestListing>`.
     ///
-    /// Can only be called internally by this actor.
-    WrapExecute(MessageId, u32),
+    /// `RunMode::IgnoredOnly` lists only ignored tests, otherwise all of them are listed.
+    /// Can be called externally.
+    List {
+        filter: TestFilter,
+        run_mode: RunMode,
+    },
+
+    /// Execute single test or hook (session id, index) with fixtures of preceding hooks,
+    /// to try catch panic if any.
+    ///
+    /// Can only be called internally by this actor, replies with `ControlError::Unauthorized`
+    /// otherwise.
+    WrapExecute(MessageId, u32, Fixtures),
+
+    /// Abort running session by id (id of its `Test` message), replies with `()`.
+    ///
+    /// Tests already running finish, but nothing is reported for them. Can be called externally,
+    /// by actors allowed with `Access` at init.
+    Abort(MessageId),
+
+    /// List running sessions, replies with `Vec<SessionInfo>`.
+    ///
+    /// Can be called externally.
+    Sessions,
 }
 
 impl ControlSignal {
-    pub fn current() -> Self {
-        gstd::msg::load::<ControlSignal>().expect("Failed to decode control signal")
+    pub fn current() -> Result<Self, ControlError> {
+        gstd::msg::load::<ControlSignal>().map_err(|e| ControlError::UndecodableSignal {
+            protocol_version: PROTOCOL_VERSION,
+            error: gstd::string::ToString::to_string(&e),
+        })
     }
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn run_tests(ptr: *const u8) {
+/// Test runner entry point, exported as `run_tests` by `gear_test_codegen::runtime`.
+///
+/// # Safety
+///
+/// `ptr` should point to the test function table stored by `wasm-test-extractor`.
+#[doc(hidden)]
+pub unsafe fn run_tests(ptr: *const u8) {
     includes::run_tests(ptr)
 }
+
+/// Test program init, exported as `init_tests` by `gear_test_codegen::runtime`.
+///
+/// `wasm-test-extractor` makes it the `init` of test binary, so that payload is `Access`.
+#[doc(hidden)]
+pub fn init_tests() {
+    access::init()
+}
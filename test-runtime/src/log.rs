@@ -0,0 +1,32 @@
+#![allow(static_mut_refs)]
+
+use gstd::{prelude::*, MessageId};
+
+// Lines logged by tests (and hooks) being executed, by `WrapExecute` message id, along with the
+// session they run for.
+//
+// Execution keeps its message id across awaits, so that concurrent tests do not mix their logs.
+// Panic reverts lines logged since the last await only, the rest were kept when it waited.
+static mut LOGS: Vec<(MessageId, MessageId, Vec<String>)> = Vec::new();
+
+pub(crate) fn push(execution: MessageId, session_id: MessageId, line: String) {
+    let logs = unsafe { &mut LOGS };
+    match logs.iter_mut().find(|(id, _, _)| *id == execution) {
+        Some((_, _, lines)) => lines.push(line),
+        None => logs.push((execution, session_id, vec![line])),
+    }
+}
+
+/// Takes lines logged by the execution so far, whether it finished or not.
+pub(crate) fn take(execution: &MessageId) -> Vec<String> {
+    let logs = unsafe { &mut LOGS };
+    match logs.iter().position(|(id, _, _)| id == execution) {
+        Some(pos) => logs.swap_remove(pos).2,
+        None => Vec::new(),
+    }
+}
+
+/// Drops lines of sessions not kept, e.g. logged by timed out test which is still running.
+pub(crate) fn retain_sessions(keep: impl Fn(&MessageId) -> bool) {
+    unsafe { &mut LOGS }.retain(|(_, session_id, _)| keep(session_id));
+}
@@ -1,16 +1,49 @@
-use super::ProgressSignal;
-use gstd::{msg, prelude::*, sync::RwLock, ActorId, CodeId, MessageId};
+use super::{log, ProgressSignal, SessionInfo, TestInfo, TestUpdate};
+use codec::Decode;
+use gstd::{exec, msg, prelude::*, sync::RwLock, ActorId, CodeId, MessageId};
+
+/// Sessions older than this are dropped, in case their `Test` message never finished
+/// (e.g. trapped or ran out of gas), unless `Test` sets other expiry.
+pub const SESSION_EXPIRY_BLOCKS: u32 = 10_000;
 
 #[derive(Debug)]
 struct Session {
     init_message: MessageId,
+    started_at: u32,
+    expiry_blocks: u32,
     data: SessionData,
 }
 
+impl Session {
+    fn is_expired(&self, now: u32) -> bool {
+        now.saturating_sub(self.started_at) > self.expiry_blocks
+    }
+}
+
+/// Encoded outputs of `before_all` / `before_each` hooks, by full hook path.
+pub type Fixtures = Vec<(String, Vec<u8>)>;
+
+/// Code ids of programs testee depends on, by name.
+pub type Codes = Vec<(String, CodeId)>;
+
+pub(crate) fn set_fixture(fixtures: &mut Fixtures, name: &str, value: Vec<u8>) {
+    match fixtures
+        .iter_mut()
+        .find(|(fixture_name, _)| fixture_name == name)
+    {
+        Some((_, fixture)) => *fixture = value,
+        None => fixtures.push((name.to_string(), value)),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionData {
     code_hash: CodeId,
+    codes: Codes,
     control_bus: ActorId,
+    fixtures: Fixtures,
+    /// Session the test or hook is executed for, lines are logged only then.
+    execution_of: Option<MessageId>,
 }
 
 impl SessionData {
@@ -18,81 +51,209 @@ impl SessionData {
         self.code_hash.clone()
     }
 
+    /// Code id of the dependency, passed with this name along with the testee.
+    ///
+    /// Panics if there is no such code.
+    pub fn code(&self, name: &str) -> CodeId {
+        self.codes
+            .iter()
+            .find(|(code_name, _)| code_name == name)
+            .map(|(_, code_id)| *code_id)
+            .unwrap_or_else(|| panic!("Code `{}` not found", name))
+    }
+
+    /// Value returned by `before_all` or `before_each` hook with this function name, or full
+    /// path (`module::hook`) if hooks of the same name in different modules run for the test.
+    ///
+    /// Panics if there is no such fixture, it is ambiguous or of different type.
+    pub fn fixture<T: Decode>(&self, name: &str) -> T {
+        let mut matching = self.fixtures.iter().filter(|(path, _)| {
+            if name.contains("::") {
+                path == name
+            } else {
+                path.rsplit("::").next() == Some(name)
+            }
+        });
+        let (_, fixture) = matching
+            .next()
+            .unwrap_or_else(|| panic!("Fixture `{}` not found", name));
+        if matching.next().is_some() {
+            panic!("Fixture `{}` is ambiguous, use full path of the hook", name);
+        }
+
+        T::decode(&mut &fixture[..])
+            .unwrap_or_else(|e| panic!("Failed to decode fixture `{}`: {:?}", name, e))
+    }
+
     fn send_progress(&self, msg: ProgressSignal) {
         let _ = msg::send(self.control_bus, msg, 0);
     }
 
+    /// Logs line for the currently executed test (or the test hook runs for).
+    ///
+    /// Unlike `gstd::debug!`, lines are buffered per execution and shipped with the final
+    /// update of the test, so that runner can show them next to the test (by default only if it
+    /// failed). Lines of the test which panics or times out are shipped too, except ones logged
+    /// since its last `await` before the panic, reverted along with the rest of its state.
+    pub fn log(&self, line: impl Into<String>) {
+        let line = line.into();
+        gstd::debug!("test log: {}", line);
+
+        if let Some(session_id) = self.execution_of {
+            log::push(msg::id(), session_id, line);
+        }
+    }
+
     pub fn test_start(&self, index: u32, name: &str) {
         gstd::debug!("test starts: {}", name);
         self.send_progress(ProgressSignal::new(index, name.to_string()));
     }
 
-    pub fn test_success(&self, index: u32, name: &str) {
-        gstd::debug!("test success: {}", name);
-        self.send_progress(ProgressSignal::new(index, name.to_string()).success());
+    pub fn test_hook_failed(&self, index: u32, name: &str, hint: String, logs: Vec<String>) {
+        gstd::debug!("test hook failed: {}", name);
+        self.send_progress(
+            ProgressSignal::new(index, name.to_string())
+                .hook_failed(hint)
+                .with_logs(logs),
+        )
+    }
+
+    /// Reports final update of the test, whatever it is.
+    pub fn test_update(&self, index: u32, name: &str, update: TestUpdate, logs: Vec<String>) {
+        gstd::debug!("test update: {}: {:?}", name, update);
+        self.send_progress(ProgressSignal {
+            test_info: TestInfo {
+                index,
+                name: name.to_string(),
+            },
+            update,
+            logs,
+        })
     }
 
-    pub fn test_fail(&self, index: u32, name: &str, hint: String) {
-        gstd::debug!("test fail: {}", name);
-        self.send_progress(ProgressSignal::new(index, name.to_string()).fail(hint))
+    pub fn test_retry(
+        &self,
+        index: u32,
+        name: &str,
+        attempt: u32,
+        hint: String,
+        logs: Vec<String>,
+    ) {
+        gstd::debug!("test attempt {} failed: {}", attempt, name);
+        self.send_progress(
+            ProgressSignal::new(index, name.to_string())
+                .retry(attempt, hint)
+                .with_logs(logs),
+        )
+    }
+
+    pub fn test_skipped(&self, index: u32, name: &str) {
+        gstd::debug!("test skipped: {}", name);
+        self.send_progress(ProgressSignal::new(index, name.to_string()).skipped())
+    }
+
+    pub fn test_ignored(&self, index: u32, name: &str, reason: Option<String>) {
+        gstd::debug!("test ignored: {}", name);
+        self.send_progress(ProgressSignal::new(index, name.to_string()).ignored(reason))
     }
 }
 
 // Vec is good enough if not much simultaneous sessions
 static SESSIONS: RwLock<Vec<Session>> = RwLock::new(Vec::new());
-static mut ACTIVE_SESSION: Option<SessionData> = None;
 
-pub async fn new_session(code_hash: CodeId, control_bus: ActorId) -> (MessageId, SessionData) {
+pub async fn new_session(
+    code_hash: CodeId,
+    codes: Codes,
+    control_bus: ActorId,
+    expiry_blocks: Option<u32>,
+) -> (MessageId, SessionData) {
     let data = SessionData {
         code_hash,
+        codes,
         control_bus,
+        fixtures: Fixtures::new(),
+        execution_of: None,
     };
     let init_message = msg::id();
-    SESSIONS.write().await.push(Session {
+    let now = exec::block_height();
+
+    let mut sessions = SESSIONS.write().await;
+    // cleaning up sessions which did not finish normally
+    sessions.retain(|session| !session.is_expired(now));
+    log::retain_sessions(|session_id| {
+        sessions
+            .iter()
+            .any(|session| &session.init_message == session_id)
+    });
+    sessions.push(Session {
         init_message: init_message.clone(),
+        started_at: now,
+        expiry_blocks: expiry_blocks.unwrap_or(SESSION_EXPIRY_BLOCKS),
         data: data.clone(),
     });
 
     (init_message, data)
 }
 
-/// Locate existing session.
-///
-/// If section is not found, panics.
-pub async fn locate_session(init_message: &MessageId) -> SessionData {
+/// Locate existing session, not aborted and not expired.
+pub async fn locate_session(init_message: &MessageId) -> Option<SessionData> {
     let sessions = SESSIONS.read().await;
+    let now = exec::block_height();
 
-    let found_session = sessions
+    sessions
         .iter()
-        .find(|session| &session.init_message == init_message)
-        .expect("Session not found. Terminating");
+        .find(|session| &session.init_message == init_message && !session.is_expired(now))
+        .map(|session| session.data.clone())
+}
 
-    found_session.data.clone()
+pub async fn is_active(init_message: &MessageId) -> bool {
+    locate_session(init_message).await.is_some()
 }
 
-pub async fn drop_session(init_message: &MessageId) {
+/// Drops session, returns `false` if there is no such session.
+pub async fn drop_session(init_message: &MessageId) -> bool {
     let mut sessions = SESSIONS.write().await;
 
-    let found_index = sessions
+    match sessions
         .iter()
         .position(|session| &session.init_message == init_message)
-        .expect("Session not found. Terminating");
-
-    sessions.swap_remove(found_index);
+    {
+        Some(found_index) => {
+            sessions.swap_remove(found_index);
+            log::retain_sessions(|session_id| session_id != init_message);
+            true
+        }
+        None => false,
+    }
 }
 
-pub fn active_session() -> SessionData {
-    unsafe {
-        ACTIVE_SESSION
-            .as_ref()
-            .expect("Failed to find active session")
-            .clone()
-    }
+/// Sessions not aborted and not expired.
+pub async fn session_infos() -> Vec<SessionInfo> {
+    let now = exec::block_height();
+
+    SESSIONS
+        .read()
+        .await
+        .iter()
+        .filter(|session| !session.is_expired(now))
+        .map(|session| SessionInfo {
+            id: session.init_message,
+            code_hash: session.data.code_hash,
+            control_bus: session.data.control_bus,
+            started_at: session.started_at,
+        })
+        .collect()
 }
 
-pub(crate) async fn set_active_session(init_message: &MessageId) {
-    let session_data = locate_session(init_message).await;
-    unsafe {
-        ACTIVE_SESSION = Some(session_data);
-    }
+/// Session data for the single `WrapExecute`, with fixtures of preceding hooks.
+///
+/// Owned by the executed test future, so that concurrent sessions do not see each other.
+pub(crate) async fn execution_session(
+    init_message: &MessageId,
+    fixtures: Fixtures,
+) -> Option<SessionData> {
+    let mut session_data = locate_session(init_message).await?;
+    session_data.fixtures = fixtures;
+    session_data.execution_of = Some(*init_message);
+    Some(session_data)
 }